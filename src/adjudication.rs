@@ -0,0 +1,207 @@
+use crate::bboard::BBoard;
+use crate::state::{BBPiece, ChessState};
+
+/// resign when the search scores the position this far below zero ...
+pub const DEFAULT_RESIGN_SCORE: i32 = 900;
+/// ... for this many moves in a row
+pub const DEFAULT_RESIGN_MOVES: u32 = 3;
+
+/// a draw offer is accepted when worse by this margin, or in an endgame when not better by it
+const DRAW_MARGIN: i32 = 50;
+
+/// non-pawn material of both sides together, below which the game counts as an endgame
+const ENDGAME_MATERIAL: i32 = 1300;
+
+/// draw offers are declined before this move, unless clearly worse
+const OPENING_MOVES: u32 = 10;
+
+/// squares of the same color as a1
+const DARK_SQUARES: BBoard = 0xaa55_aa55_aa55_aa55;
+
+/// Decides when to give up a game and when to agree to a draw, from the search scores.
+#[derive(Clone, Debug)]
+pub struct Adjudicator {
+    pub resign_score: i32,
+    /// zero never resigns
+    pub resign_moves: u32,
+    pub offer_draws: bool,
+
+    /// searches in a row scoring below the resign score
+    lost_moves: u32,
+    /// score of our last search in this game
+    score: i32,
+}
+
+impl Default for Adjudicator {
+    fn default() -> Self {
+        Adjudicator::new()
+    }
+}
+
+impl Adjudicator {
+    pub fn new() -> Adjudicator {
+        Adjudicator {
+            resign_score: DEFAULT_RESIGN_SCORE,
+            resign_moves: DEFAULT_RESIGN_MOVES,
+            offer_draws: true,
+            lost_moves: 0,
+            score: 0,
+        }
+    }
+
+    /// Forgets the scores of the previous game.
+    pub fn reset(&mut self) {
+        self.lost_moves = 0;
+        self.score = 0;
+    }
+
+    /// Takes the score of our last search in this game.
+    pub fn record_score(&mut self, score: i32) {
+        self.score = score;
+
+        if score <= -self.resign_score {
+            self.lost_moves += 1;
+        } else {
+            self.lost_moves = 0;
+        }
+    }
+
+    /// Tells if the recorded scores make it time to resign.
+    pub fn should_resign(&self) -> bool {
+        self.resign_moves > 0 && self.lost_moves >= self.resign_moves
+    }
+
+    /// Answers a draw offer given the score of our last search: taken when clearly worse,
+    /// or in an endgame with nothing to play for, but not in the opening.
+    pub fn accept_draw(&self, state: &ChessState) -> bool {
+        let score = self.score;
+
+        if score <= -DRAW_MARGIN {
+            return true;
+        }
+
+        state.full_move_count >= OPENING_MOVES && non_pawn_material(state) <= ENDGAME_MATERIAL && score <= DRAW_MARGIN
+    }
+}
+
+/// Value of the knights, bishops, rooks and queens of both sides.
+fn non_pawn_material(state: &ChessState) -> i32 {
+    BBPiece::get_pieces()
+        .iter()
+        .filter(|piece| !matches!(piece, BBPiece::WKing | BBPiece::BKing | BBPiece::WPawn | BBPiece::BPawn))
+        .map(|piece| state.bboard(*piece).count_ones() as i32 * piece.value().abs())
+        .sum()
+}
+
+/// Tells if neither side has the material left to checkmate: bare kings, a single minor piece,
+/// or only bishops all standing on squares of the same color.
+pub fn is_dead_draw(state: &ChessState) -> bool {
+    let heavy = [BBPiece::WPawn, BBPiece::BPawn, BBPiece::WRook, BBPiece::BRook, BBPiece::WQueen, BBPiece::BQueen];
+
+    if heavy.iter().any(|piece| state.bboard(*piece) != 0) {
+        return false;
+    }
+
+    let knights = state.bboard(BBPiece::WKnight) | state.bboard(BBPiece::BKnight);
+    let bishops = state.bboard(BBPiece::WBishop) | state.bboard(BBPiece::BBishop);
+
+    if (knights | bishops).count_ones() <= 1 {
+        return true;
+    }
+
+    knights == 0 && (bishops & DARK_SQUARES == 0 || bishops & !DARK_SQUARES == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_setup::{ChessMove, GameSetup};
+
+    #[test]
+    fn test_resign() {
+        let mut adjudicator = Adjudicator::new();
+        let resigns = |adjudicator: &mut Adjudicator, score| {
+            adjudicator.record_score(score);
+            adjudicator.should_resign()
+        };
+
+        assert!(!resigns(&mut adjudicator, -1000));
+        assert!(!resigns(&mut adjudicator, -1000));
+        // a better score starts the count again
+        assert!(!resigns(&mut adjudicator, -300));
+        assert!(!resigns(&mut adjudicator, -1000));
+        assert!(!resigns(&mut adjudicator, -1000));
+        assert!(resigns(&mut adjudicator, -1000));
+
+        // asking again changes nothing
+        assert!(adjudicator.should_resign());
+
+        adjudicator.reset();
+        assert!(!adjudicator.should_resign());
+        adjudicator.resign_moves = 0;
+        assert!((0..10).all(|_| !resigns(&mut adjudicator, -1000)));
+    }
+
+    #[test]
+    fn test_accept_draw() {
+        let mut adjudicator = Adjudicator::new();
+
+        let opening = ChessState::new_game();
+        assert!(!adjudicator.accept_draw(&opening));
+        adjudicator.record_score(-200);
+        assert!(adjudicator.accept_draw(&opening));
+
+        // the score of the previous game does not count
+        adjudicator.reset();
+        assert!(!adjudicator.accept_draw(&opening));
+
+        let endgame = ChessState::from_fen("8/5pk1/8/8/8/8/3R1PK1/8 w - - 0 40");
+        adjudicator.record_score(30);
+        assert!(adjudicator.accept_draw(&endgame));
+        adjudicator.record_score(400);
+        assert!(!adjudicator.accept_draw(&endgame));
+    }
+
+    #[test]
+    fn test_dead_draw() {
+        let dead = [
+            "8/8/4k3/8/8/3K4/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3KN3/8/8 w - - 0 1",
+            "8/8/4kb2/8/8/3KB3/8/8 w - - 0 1",
+        ];
+
+        for fen in dead.iter() {
+            assert!(is_dead_draw(&ChessState::from_fen(fen)), "{}", fen);
+        }
+
+        let alive = [
+            "8/8/4k3/8/8/3KP3/8/8 w - - 0 1",
+            "8/8/4k3/8/8/2NKN3/8/8 w - - 0 1",
+            "8/8/4k1b1/8/8/3KB3/8/8 w - - 0 1",
+        ];
+
+        for fen in alive.iter() {
+            assert!(!is_dead_draw(&ChessState::from_fen(fen)), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_repetitions() {
+        let mut setup = GameSetup::new();
+
+        for _ in 0..2 {
+            for coords in ["g1f3", "g8f6", "f3g1", "f6g8"].iter() {
+                let parsed = ChessMove::parse(coords, &setup.game_state).unwrap();
+                let legal = setup.engine.legal_move(&mut setup.game_state, &parsed).unwrap();
+                setup.play_move(legal);
+            }
+        }
+
+        assert_eq!(setup.repetitions(), 3);
+        assert_eq!(setup.history_keys().len(), 8);
+        assert_eq!(setup.history_keys()[0], setup.game_state.hash_key());
+
+        setup.undo_move();
+        assert_eq!(setup.repetitions(), 2);
+    }
+}
@@ -1,107 +1,406 @@
-use std::num::Wrapping;
+use std::fmt;
+use std::ops;
 
 pub type BBoard = u64;
 
+pub fn bb_coord(x: u8, y: u8) -> BBoard {
+    assert!(x < 8);
+    assert!(y < 8);
+    Square::new(File(x), Rank(y)).bit()
+}
+
+pub fn bb_coord_q(x: i32, y: i32) -> BBoard {
+    Square::from_coords(x, y).map_or(0, |square| square.bit())
+}
+
+pub fn bb_print(board: BBoard) {
+    print!("{}", Bitboard(board));
+}
+
+pub fn bb_get_q(board: BBoard, x: i32, y: i32) -> bool {
+    Square::from_coords(x, y).is_some_and(|square| Bitboard(board).contains(square))
+}
+
+pub fn bb_to_coord(board: BBoard) -> String {
+    match Bitboard(board).single_square() {
+        Some(square) => square.to_string(),
+        None => String::from("-"),
+    }
+}
+
 #[inline]
-pub fn last_bit(b: BBoard) -> BBoard {
-    b & (-Wrapping(b)).0
+pub fn add_bit(board: &mut BBoard, x: u32, y: u32) {
+    if let Some(square) = Square::from_coords(x as i32, y as i32) {
+        *board |= square.bit();
+    }
 }
 
 #[inline]
-pub fn remove_last_bit(b: BBoard) -> BBoard {
-    assert_ne!(b, 0);
-    b & (b - 1)
+pub fn has_bit(board: &BBoard, x: u32, y: u32) -> bool {
+    bb_get_q(*board, x as i32, y as i32)
 }
 
-pub fn bb_coord(x: u8, y: u8) -> BBoard {
-    assert!(x < 8);
-    assert!(y < 8);
-    1u64 << (y * 8 + x) as u64
+/// Board file, 0 for the a-file up to 7 for the h-file
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct File(pub u8);
+
+/// Board rank, 0 for the first rank up to 7 for the eighth
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Rank(pub u8);
+
+/// Board square, 0 for a1, 7 for h1 up to 63 for h8
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Square(u8);
+
+/// Set of squares with the bit of each square at its index
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Bitboard(pub BBoard);
+
+const FILE_A: BBoard = 0x0101_0101_0101_0101;
+const RANK_1: BBoard = 0xff;
+const DIAGONAL_A1_H8: BBoard = 0x8040_2010_0804_0201;
+const ANTI_DIAGONAL_H1_A8: BBoard = 0x0102_0408_1020_4080;
+
+impl File {
+    pub const A: File = File(0);
+    pub const H: File = File(7);
+
+    #[inline]
+    pub const fn idx(self) -> usize {
+        self.0 as usize
+    }
+
+    /// all squares of the file
+    #[inline]
+    pub const fn mask(self) -> Bitboard {
+        Bitboard(FILE_A << self.0)
+    }
+
+    pub fn to_char(self) -> char {
+        (b'a' + self.0) as char
+    }
 }
 
-pub fn bb_coord_q(x: i32, y: i32) -> BBoard {
-    if x > 7 || x < 0 {
-        return 0;
+impl Rank {
+    pub const R1: Rank = Rank(0);
+    pub const R2: Rank = Rank(1);
+    pub const R4: Rank = Rank(3);
+    pub const R5: Rank = Rank(4);
+    pub const R7: Rank = Rank(6);
+    pub const R8: Rank = Rank(7);
+
+    #[inline]
+    pub const fn idx(self) -> usize {
+        self.0 as usize
     }
-    if y > 7 || y < 0 {
-        return 0;
+
+    /// all squares of the rank
+    #[inline]
+    pub const fn mask(self) -> Bitboard {
+        Bitboard(RANK_1 << (self.0 * 8))
     }
 
-    1u64 << (y * 8 + x) as u64
+    pub fn to_char(self) -> char {
+        (b'1' + self.0) as char
+    }
 }
 
-pub fn bb_print(board: BBoard) {
-    // print board
-    for y in 0..8 {
-        for x in 0..8 {
-            if has_bit(&board, x, 7-y) {
-                print!("*");
-            } else {
-                print!(".");
-            }
+impl Square {
+    #[inline]
+    pub const fn new(file: File, rank: Rank) -> Square {
+        debug_assert!(file.0 < 8 && rank.0 < 8);
+        Square(rank.0 * 8 + file.0)
+    }
+
+    #[inline]
+    pub const fn from_idx(idx: usize) -> Square {
+        debug_assert!(idx < 64);
+        Square(idx as u8)
+    }
+
+    /// Square of a single bit board, like the move from and to boards.
+    #[inline]
+    pub fn from_bit(bit: BBoard) -> Square {
+        debug_assert_eq!(bit.count_ones(), 1);
+        Square(bit.trailing_zeros() as u8)
+    }
+
+    /// Square of the file and rank numbers, `None` when off the board.
+    #[inline]
+    pub fn from_coords(x: i32, y: i32) -> Option<Square> {
+        if (0..8).contains(&x) && (0..8).contains(&y) {
+            Some(Square::new(File(x as u8), Rank(y as u8)))
+        } else {
+            None
         }
+    }
 
-        println!();
+    /// Parses the coordinate notation, like `e4`.
+    pub fn parse(text: &str) -> Option<Square> {
+        match text.as_bytes() {
+            [x @ b'a'..=b'h', y @ b'1'..=b'8'] => Some(Square::new(File(x - b'a'), Rank(y - b'1'))),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub const fn idx(self) -> usize {
+        self.0 as usize
+    }
+
+    #[inline]
+    pub const fn file(self) -> File {
+        File(self.0 % 8)
+    }
+
+    #[inline]
+    pub const fn rank(self) -> Rank {
+        Rank(self.0 / 8)
+    }
+
+    /// single bit board of the square
+    #[inline]
+    pub const fn bit(self) -> BBoard {
+        1u64 << self.0
+    }
+
+    #[inline]
+    pub const fn bitboard(self) -> Bitboard {
+        Bitboard(self.bit())
+    }
+
+    /// the same square seen from the other side of the board
+    #[inline]
+    pub const fn flip(self) -> Square {
+        Square(self.0 ^ 56)
+    }
+
+    /// squares of the a1-h8 direction diagonal through the square
+    pub const fn diagonal(self) -> Bitboard {
+        // vertical shifts move the main diagonal without wrapping around the board
+        let shift = self.rank().0 as i32 - self.file().0 as i32;
+
+        if shift >= 0 {
+            Bitboard(DIAGONAL_A1_H8 << (shift * 8))
+        } else {
+            Bitboard(DIAGONAL_A1_H8 >> (-shift * 8))
+        }
+    }
+
+    /// squares of the h1-a8 direction diagonal through the square
+    pub const fn anti_diagonal(self) -> Bitboard {
+        let shift = self.file().0 as i32 + self.rank().0 as i32 - 7;
+
+        if shift >= 0 {
+            Bitboard(ANTI_DIAGONAL_H1_A8 << (shift * 8))
+        } else {
+            Bitboard(ANTI_DIAGONAL_H1_A8 >> (-shift * 8))
+        }
     }
 }
 
-pub fn bb_get_q(board: BBoard, x: i32, y: i32) -> bool {
-    if x > 7 || x < 0 {
-        return false;
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.file().to_char(), self.rank().to_char())
+    }
+}
+
+impl Bitboard {
+    pub const EMPTY: Bitboard = Bitboard(0);
+    pub const FULL: Bitboard = Bitboard(!0);
+
+    #[inline]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
     }
 
-    if y > 7 || y < 0 {
-        return false;
+    #[inline]
+    pub const fn count(self) -> u32 {
+        self.0.count_ones()
     }
 
-    board & 1u64 << (y * 8 + x) as u64 > 0
+    #[inline]
+    pub const fn contains(self, square: Square) -> bool {
+        self.0 & square.bit() != 0
+    }
+
+    /// lowest square of the set
+    #[inline]
+    pub fn first(self) -> Option<Square> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(Square(self.0.trailing_zeros() as u8))
+        }
+    }
+
+    /// the square, when the set has exactly one
+    #[inline]
+    pub fn single_square(self) -> Option<Square> {
+        if self.count() == 1 {
+            self.first()
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub const fn north(self) -> Bitboard {
+        Bitboard(self.0 << 8)
+    }
+
+    #[inline]
+    pub const fn south(self) -> Bitboard {
+        Bitboard(self.0 >> 8)
+    }
+
+    #[inline]
+    pub const fn east(self) -> Bitboard {
+        Bitboard((self.0 << 1) & !FILE_A)
+    }
+
+    #[inline]
+    pub const fn west(self) -> Bitboard {
+        Bitboard((self.0 >> 1) & !(FILE_A << 7))
+    }
 }
 
-pub fn bb_to_coord(board: BBoard) -> String {
-    if board.count_ones() == 1 {
-        let idx: u32 = board.trailing_zeros();
-        let y = idx / 8;
-        let x = idx % 8;
-        String::from(format!(
-            "{}{}",
-            (x as u8 + b'a') as char,
-            (y as u8 + b'1') as char
-        ))
-    } else {
-        String::from("-")
+/// Squares of a bit board from the lowest to the highest.
+pub struct Squares(BBoard);
+
+impl Iterator for Squares {
+    type Item = Square;
+
+    #[inline]
+    fn next(&mut self) -> Option<Square> {
+        if self.0 == 0 {
+            return None;
+        }
+
+        let square = Square(self.0.trailing_zeros() as u8);
+        self.0 &= self.0 - 1;
+
+        Some(square)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let count = self.0.count_ones() as usize;
+        (count, Some(count))
     }
 }
 
-#[inline]
-pub fn add_bit(board: &mut BBoard, x: u32, y: u32) {
-    if x > 7 {
-        return;
+impl IntoIterator for Bitboard {
+    type Item = Square;
+    type IntoIter = Squares;
+
+    #[inline]
+    fn into_iter(self) -> Squares {
+        Squares(self.0)
+    }
+}
+
+impl From<BBoard> for Bitboard {
+    #[inline]
+    fn from(board: BBoard) -> Bitboard {
+        Bitboard(board)
     }
-    
-    if y > 7 {
-        return;
+}
+
+impl From<Bitboard> for BBoard {
+    #[inline]
+    fn from(board: Bitboard) -> BBoard {
+        board.0
     }
+}
 
-    (*board) |= 1u64 << (x + y * 8) as u64;
+impl From<Square> for Bitboard {
+    #[inline]
+    fn from(square: Square) -> Bitboard {
+        square.bitboard()
+    }
 }
 
-#[inline]
-pub fn has_bit(board: &BBoard, x: u32, y: u32) -> bool {
-    if x > 7 {
-        return false;
+macro_rules! bitboard_operator {
+    ($op:ident, $fn:ident, $op_assign:ident, $fn_assign:ident, $sym:tt) => {
+        impl ops::$op for Bitboard {
+            type Output = Bitboard;
+
+            #[inline]
+            fn $fn(self, other: Bitboard) -> Bitboard {
+                Bitboard(self.0 $sym other.0)
+            }
+        }
+
+        impl ops::$op<Square> for Bitboard {
+            type Output = Bitboard;
+
+            #[inline]
+            fn $fn(self, other: Square) -> Bitboard {
+                Bitboard(self.0 $sym other.bit())
+            }
+        }
+
+        impl ops::$op_assign for Bitboard {
+            #[inline]
+            fn $fn_assign(&mut self, other: Bitboard) {
+                self.0 = self.0 $sym other.0;
+            }
+        }
+    };
+}
+
+bitboard_operator!(BitAnd, bitand, BitAndAssign, bitand_assign, &);
+bitboard_operator!(BitOr, bitor, BitOrAssign, bitor_assign, |);
+bitboard_operator!(BitXor, bitxor, BitXorAssign, bitxor_assign, ^);
+
+impl ops::Not for Bitboard {
+    type Output = Bitboard;
+
+    #[inline]
+    fn not(self) -> Bitboard {
+        Bitboard(!self.0)
     }
-    if y > 7 {
-        return false;
+}
+
+impl ops::Shl<u32> for Bitboard {
+    type Output = Bitboard;
+
+    #[inline]
+    fn shl(self, amount: u32) -> Bitboard {
+        Bitboard(self.0 << amount)
     }
+}
+
+impl ops::Shr<u32> for Bitboard {
+    type Output = Bitboard;
 
-    return (*board & (1u64 << (x + y * 8) as u64)) > 0;
+    #[inline]
+    fn shr(self, amount: u32) -> Bitboard {
+        Bitboard(self.0 >> amount)
+    }
 }
 
+impl fmt::Display for Bitboard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for rank in (0..8).rev() {
+            for file in 0..8 {
+                let square = Square::new(File(file), Rank(rank));
+                write!(f, "{}", if self.contains(square) { '*' } else { '.' })?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use std::num::Wrapping;
 
+    use super::*;
+
     #[test]
     fn test_pop_count() {
         let test3 = 1u64 << 5 | 1 << 10 | 1 << 20;
@@ -124,4 +423,45 @@ mod tests {
     fn test_pop_count_0() {
         assert_eq!(0u64.count_ones(), 0);
     }
+
+    #[test]
+    fn test_square() {
+        let e4 = Square::parse("e4").unwrap();
+
+        assert_eq!(e4.idx(), 28);
+        assert_eq!(Square::from_bit(e4.bit()), e4);
+        assert_eq!(e4.file(), File(4));
+        assert_eq!(e4.rank(), Rank::R4);
+        assert_eq!(e4.to_string(), "e4");
+        assert_eq!(e4.flip().to_string(), "e5");
+        assert_eq!(Square::parse("i1"), None);
+        assert_eq!(Square::from_coords(8, 0), None);
+
+        assert_eq!(File::A.mask().0, 0x0101_0101_0101_0101);
+        assert_eq!(Rank::R8.mask().0, 0xff00_0000_0000_0000);
+        assert_eq!(e4.diagonal().0, bits(&["b1", "c2", "d3", "e4", "f5", "g6", "h7"]));
+        assert_eq!(e4.anti_diagonal().0, bits(&["h1", "g2", "f3", "e4", "d5", "c6", "b7", "a8"]));
+        assert_eq!(Square::parse("h1").unwrap().diagonal().0, bits(&["h1"]));
+    }
+
+    #[test]
+    fn test_bitboard() {
+        let board = Bitboard(bits(&["a1", "h1", "e4", "h8"]));
+
+        let squares: Vec<String> = board.into_iter().map(|s| s.to_string()).collect();
+        assert_eq!(squares, vec!["a1", "h1", "e4", "h8"]);
+
+        assert_eq!(board.count(), 4);
+        assert_eq!(board.first(), Square::parse("a1"));
+        assert_eq!(board.single_square(), None);
+        assert_eq!((board & Rank::R1.mask()).0, bits(&["a1", "h1"]));
+        assert_eq!(board.north().0, bits(&["a2", "h2", "e5"]));
+        assert_eq!(board.east().0, bits(&["b1", "f4"]));
+        assert_eq!(board.west().0, bits(&["g1", "d4", "g8"]));
+        assert!(Bitboard::EMPTY.into_iter().next().is_none());
+    }
+
+    fn bits(squares: &[&str]) -> BBoard {
+        squares.iter().fold(0, |board, s| board | Square::parse(s).unwrap().bit())
+    }
 }
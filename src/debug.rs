@@ -13,7 +13,7 @@ impl AsString for BBoard {
         let mut result = String::new();
 
         for i in 0..8 {
-            let b: u8 = ((*self >> (7 - i) * 8) & 255) as u8;
+            let b: u8 = ((*self >> ((7 - i) * 8)) & 255) as u8;
 
             result.push_str(format!("{:08b}\n", b.reverse_bits()).as_str());
         }
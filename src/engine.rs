@@ -1,128 +1,1404 @@
-use crate::evaluator::evaluate_position;
+use crate::adjudication::is_dead_draw;
+use crate::evaluator::{evaluate_relative, EvalWeights};
 use crate::game_setup::ChessMove;
 use crate::move_generator::MoveGenerator;
-use crate::state::{ChessState, Side};
+use crate::move_picker::MovePicker;
+use crate::search_stats::{count, SearchStats};
+use crate::skill::Skill;
+use crate::state::{BBPiece, ChessState, Side};
+use crate::tt::{Bound, TranspositionTable};
 use core::cmp;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// maximal search depth in plies, including the root move
+const MAX_PLY: usize = 64;
+
+/// depth of the last iteration of a normal search
+pub const DEFAULT_DEPTH: u32 = 5;
+
+/// depth of the last iteration of an infinite search, leaving room for the extensions
+pub const INFINITE_DEPTH: u32 = MAX_PLY as u32 / 2;
+
+/// default size of the transposition table in megabytes
+pub const DEFAULT_HASH_MB: usize = 16;
+
+/// most search threads accepted by `set_threads`
+pub const MAX_THREADS: usize = 64;
+
+/// most lines reported by a multi-PV search
+pub const MAX_MULTI_PV: usize = 64;
+
+/// score of being checkmated at the root, mates further away score closer to zero
+pub const MATE_SCORE: i32 = 100000;
+
+/// bound beyond any score, safe to negate
+pub const INFINITE_SCORE: i32 = 200000;
+
+/// Tells if the score is a checkmate found by the search, for either side.
+#[inline]
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE_SCORE - MAX_PLY as i32
+}
+
+/// Progress of the search after a completed iteration of the main thread.
+#[derive(Clone, Debug)]
+pub struct Iteration {
+    pub depth: u32,
+    /// number of the line in a multi-PV search, 1 for the best one
+    pub line: usize,
+    pub score: i32,
+    /// nodes searched by the main thread
+    pub nodes: u64,
+    pub time: Duration,
+    /// number of root moves
+    pub moves: usize,
+    /// the line, starting with its root move
+    pub pv: Vec<ChessMove>,
+}
+
+/// Called with every line of each completed iteration, for the thinking output.
+pub type IterationListener = Box<dyn FnMut(&Iteration) + Send>;
+
+/// Tuning of the selective search. Each pruning can be switched off for testing.
+#[derive(Copy, Clone, Debug)]
+pub struct SearchParams {
+    /// try passing the move first and cut off when the opponent still can not catch up
+    pub null_move: bool,
+    pub null_move_min_depth: u32,
+    pub null_move_reduction: u32,
+    /// extra reduction of the null move search from this depth on
+    pub null_move_deep_depth: u32,
+
+    /// search quiet moves late in the move order with a reduced depth
+    pub late_move_reductions: bool,
+    pub lmr_min_depth: u32,
+    /// number of moves searched at the full depth before reducing
+    pub lmr_full_moves: usize,
+    pub lmr_reduction: u32,
+
+    /// search one ply deeper after checking moves
+    pub check_extensions: bool,
+    /// search one ply deeper when the side in check has a single legal move
+    pub one_reply_extensions: bool,
+    /// search the hash move one ply deeper when all the other moves fail low
+    /// against its table score lowered by the margin per ply
+    pub singular_extensions: bool,
+    pub singular_min_depth: u32,
+    pub singular_margin: i32,
+    /// maximal number of extensions along one path from the root
+    pub extension_budget: u32,
+
+    /// skip quiet moves close to the horizon when the static eval plus the margin
+    /// per remaining ply stays below alpha
+    pub futility_pruning: bool,
+    pub futility_depth: u32,
+    pub futility_margin: i32,
+    /// return the static eval close to the horizon when it beats beta by the margin per ply
+    pub reverse_futility_pruning: bool,
+    pub reverse_futility_depth: u32,
+    pub reverse_futility_margin: i32,
+    /// drop into the quiescence search when the static eval is far below alpha
+    pub razoring: bool,
+    pub razoring_depth: u32,
+    pub razoring_margin: i32,
+
+    /// depth of the last iteration, the root moves count as one ply
+    pub max_depth: u32,
+    /// search the iterations from this depth on with a window around the previous score
+    pub aspiration_min_depth: u32,
+    pub aspiration_window: i32,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        SearchParams {
+            null_move: true,
+            null_move_min_depth: 3,
+            null_move_reduction: 2,
+            null_move_deep_depth: 6,
+            late_move_reductions: true,
+            lmr_min_depth: 3,
+            lmr_full_moves: 4,
+            lmr_reduction: 1,
+            check_extensions: true,
+            one_reply_extensions: true,
+            singular_extensions: true,
+            singular_min_depth: 4,
+            singular_margin: 20,
+            extension_budget: 8,
+            futility_pruning: true,
+            futility_depth: 2,
+            futility_margin: 150,
+            reverse_futility_pruning: true,
+            reverse_futility_depth: 3,
+            reverse_futility_margin: 120,
+            razoring: true,
+            razoring_depth: 2,
+            razoring_margin: 300,
+            max_depth: DEFAULT_DEPTH,
+            aspiration_min_depth: 3,
+            aspiration_window: 50,
+        }
+    }
+}
 
 pub struct ChessEngine {
     move_generator: MoveGenerator,
+    killers: Vec<[Option<ChessMove>; 2]>,
+    /// hash keys of the positions played in the game before the searched one
+    game_keys: Vec<u64>,
+    /// hash keys of the positions on the path from the root, by ply
+    path_keys: Vec<u64>,
+    pub params: SearchParams,
+    pub weights: EvalWeights,
+
+    /// shared by the threads of a Lazy SMP search
+    tt: Arc<TranspositionTable>,
+    /// tells the helper threads to give up once the main thread has finished,
+    /// also set from outside to interrupt the search
+    stop: Arc<AtomicBool>,
+
+    threads: usize,
+    /// zero for the main thread, the helpers vary their depth by it
+    thread_id: usize,
+
+    /// counters of the last search, of all threads
+    stats: SearchStats,
+    /// score of the last search for the side to move
+    score: i32,
+
+    skill: Skill,
+    /// random source of the move choice of the limited skill levels
+    rng: StdRng,
+    /// nodes searched by this thread, the search stops at the limit
+    node_count: u64,
+    node_limit: Option<u64>,
+    /// scores of the root moves in the order of the last completed iteration, for the skill levels
+    root_scores: Vec<i32>,
+
+    /// search until stopped instead of up to `max_depth`
+    infinite: bool,
+    /// number of best root moves reported with their own score and line
+    multi_pv: usize,
+    listener: Option<IterationListener>,
+    start: Instant,
+}
+
+impl Default for ChessEngine {
+    fn default() -> Self {
+        ChessEngine::new()
+    }
 }
 
 impl ChessEngine {
     pub fn new() -> ChessEngine {
         ChessEngine {
             move_generator: MoveGenerator::new(),
+            killers: vec![[None, None]; MAX_PLY],
+            game_keys: Vec::new(),
+            path_keys: vec![0; MAX_PLY],
+            params: SearchParams::default(),
+            weights: EvalWeights::default(),
+            tt: Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)),
+            stop: Arc::new(AtomicBool::new(false)),
+            threads: 1,
+            thread_id: 0,
+            stats: SearchStats::default(),
+            score: 0,
+            skill: Skill::default(),
+            rng: StdRng::from_entropy(),
+            node_count: 0,
+            node_limit: None,
+            root_scores: Vec::new(),
+            infinite: false,
+            multi_pv: 1,
+            listener: None,
+            start: Instant::now(),
         }
     }
 
-    pub fn min_max_search(&self, penalty: i32, depth: u32, alpha: i32, beta: i32, state: &mut ChessState) -> i32 {
-        if depth == 0 {
-            // just estimate the current position and return its score
-            return evaluate_position(state);
+    /// Lets the iterations go on until the stop flag is set, for analysis.
+    pub fn set_infinite(&mut self, infinite: bool) {
+        self.infinite = infinite;
+    }
+
+    /// Number of best root moves to search and report separately, each with its own line.
+    pub fn set_multi_pv(&mut self, lines: usize) {
+        self.multi_pv = lines.clamp(1, MAX_MULTI_PV);
+    }
+
+    pub fn multi_pv(&self) -> usize {
+        self.multi_pv
+    }
+
+    /// Reports the completed iterations of the following searches, or stops reporting.
+    pub fn set_listener(&mut self, listener: Option<IterationListener>) {
+        self.listener = listener;
+    }
+
+    pub fn set_skill(&mut self, skill: Skill) {
+        self.skill = skill;
+    }
+
+    pub fn skill(&self) -> Skill {
+        self.skill
+    }
+
+    /// Seeds the random move choice of the limited skill levels, for reproducible games.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Seeds the random move choice from the system entropy, as a new engine does.
+    pub fn set_entropy_seed(&mut self) {
+        self.rng = StdRng::from_entropy();
+    }
+
+    /// Hash keys of the positions played before the one to search, oldest first.
+    /// The search scores a return to any of them as a draw.
+    pub fn set_game_keys(&mut self, keys: Vec<u64>) {
+        self.game_keys = keys;
+    }
+
+    /// Number of threads searching the root position together.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.clamp(1, MAX_THREADS);
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Replaces the transposition table with an empty one of the given size.
+    pub fn set_hash_size(&mut self, size_mb: usize) {
+        self.tt = Arc::new(TranspositionTable::new(size_mb));
+    }
+
+    /// Forgets the results of the previous searches, for a new game.
+    pub fn clear_hash(&mut self) {
+        self.tt.clear();
+        self.killers = vec![[None, None]; MAX_PLY];
+    }
+
+    /// Search thread sharing the table and the stop flag, with its own killers.
+    fn helper(&self, thread_id: usize) -> ChessEngine {
+        ChessEngine {
+            move_generator: self.move_generator,
+            killers: vec![[None, None]; MAX_PLY],
+            game_keys: self.game_keys.clone(),
+            path_keys: vec![0; MAX_PLY],
+            params: self.params,
+            weights: self.weights,
+            tt: self.tt.clone(),
+            stop: self.stop.clone(),
+            threads: 1,
+            thread_id,
+            stats: SearchStats::default(),
+            score: 0,
+            skill: self.skill,
+            rng: StdRng::seed_from_u64(thread_id as u64),
+            node_count: 0,
+            node_limit: None,
+            root_scores: Vec::new(),
+            infinite: self.infinite,
+            multi_pv: 1,
+            listener: None,
+            start: self.start,
         }
+    }
 
-        let mut moves: Vec<ChessMove> = Vec::with_capacity(20);
+    /// Statistics of the last search.
+    pub fn stats(&self) -> &SearchStats {
+        &self.stats
+    }
 
+    /// Score of the last search for the side to move, of its last completed iteration.
+    pub fn score(&self) -> i32 {
+        self.score
+    }
+
+    /// Flag which stops the running search when set, the search then returns the best move
+    /// of its last completed iteration. It is cleared once the search has returned.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// The move expected from the side to move, as the best move stored by the last search.
+    pub fn expected_reply(&self, state: &mut ChessState) -> Option<ChessMove> {
+        let entry = self.tt.probe(state.hash_key())?;
+        let best_move = entry.best_move(state.next_to_move)?;
+
+        self.move_generator.legal_move(state, &best_move)
+    }
+
+    /// The best line after the root move, followed through the best moves stored in the table.
+    pub fn principal_variation(&self, root_move: &ChessMove, max_len: usize, state: &mut ChessState) -> Vec<ChessMove> {
+        let mut pv = vec![root_move.clone()];
+        state.do_move(root_move);
+
+        while pv.len() < max_len {
+            match self.expected_reply(state) {
+                Some(reply) => {
+                    state.do_move(&reply);
+                    pv.push(reply);
+                }
+                None => break,
+            }
+        }
+
+        for m in pv.iter().rev() {
+            state.undo_move(m);
+        }
+
+        pv
+    }
+
+    #[inline]
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// Counts a searched node, and stops all threads when the node limit is reached.
+    #[inline]
+    fn visit_node(&mut self) {
+        self.node_count += 1;
+
+        if self.node_limit.is_some_and(|limit| self.node_count >= limit) {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the legal move matching the coordinates of the given one,
+    /// with the deltas computed for the current state.
+    pub fn legal_move(&self, state: &mut ChessState, chess_move: &ChessMove) -> Option<ChessMove> {
+        self.move_generator.legal_move(state, chess_move)
+    }
+
+    /// remember a quiet move which caused a beta cutoff at the given ply
+    fn store_killer(&mut self, ply: usize, chess_move: &ChessMove) {
+        if ply >= MAX_PLY || chess_move.is_capture() {
+            return;
+        }
+
+        let killers = &mut self.killers[ply];
+
+        if killers[0].as_ref().is_some_and(|m| m.is_same(chess_move)) {
+            return;
+        }
+
+        killers[1] = killers[0].take();
+        killers[0] = Some(chess_move.clone());
+    }
+
+    fn is_killer(killers: &[Option<ChessMove>; 2], chess_move: &ChessMove) -> bool {
+        killers.iter().flatten().any(|m| m.is_same(chess_move))
+    }
+
+    /// Searches the moves other than the hash move at half the depth with a null window,
+    /// tells if all of them fail low against `beta`, which leaves the hash move as the only good one.
+    fn is_singular(
+        &mut self,
+        ply: usize,
+        depth: u32,
+        beta: i32,
+        hash_move: &ChessMove,
+        extensions: u32,
+        state: &mut ChessState,
+    ) -> bool {
+        let mut moves: Vec<ChessMove> = Vec::new();
         self.move_generator.generate_moves(state, &mut moves);
 
-        if moves.len() == 0 {
+        // a hash move from a colliding key is not one of the moves
+        if !moves.iter().any(|m| m.is_same(hash_move)) {
+            return false;
+        }
+
+        for cur_move in moves.iter().filter(|m| !m.is_same(hash_move)) {
+            state.do_move(cur_move);
+            let score = -self.search(ply + 1, depth / 2, -beta, -beta + 1, true, extensions, state);
+            state.undo_move(cur_move);
+
+            if score >= beta {
+                return false;
+            }
+        }
+
+        !self.stopped()
+    }
+
+    /// Tells if the side to move has a move checking the opponent king.
+    fn has_checking_move(&self, state: &mut ChessState) -> bool {
+        let info = self.move_generator.check_info(state);
+
+        let mut moves: Vec<ChessMove> = Vec::new();
+        self.move_generator.generate_moves(state, &mut moves);
+
+        moves.iter().any(|m| self.move_generator.gives_check_with(state, &info, m))
+    }
+
+    /// Tells if the side has any piece besides the king and pawns. Without them
+    /// zugzwang is common, and passing the move is no safe lower bound.
+    fn has_non_pawn_material(state: &ChessState, side: Side) -> bool {
+        let ofs = side.offset();
+
+        [BBPiece::WRook, BBPiece::WKnight, BBPiece::WBishop, BBPiece::WQueen]
+            .iter()
+            .any(|piece| state.bboard_ofs(*piece, ofs) > 0)
+    }
+
+    /// Searches the position after passing the move with a reduced depth, and tells
+    /// if the opponent still can not get below beta.
+    fn null_move_cutoff(&mut self, ply: usize, depth: u32, beta: i32, state: &mut ChessState) -> bool {
+        let params = self.params;
+
+        let mut reduction = params.null_move_reduction;
+        if depth >= params.null_move_deep_depth {
+            reduction += 1;
+        }
+
+        let depth = depth.saturating_sub(1 + reduction);
+
+        let null_move = state.do_null_move();
+        let score = -self.search(ply + 1, depth, -beta, -beta + 1, false, 0, state);
+        state.undo_null_move(&null_move);
+
+        score >= beta
+    }
+
+    /// Tells if the position at the given ply repeats one of the game or of the path to it.
+    fn is_repetition(&self, ply: usize, key: u64) -> bool {
+        self.path_keys[..ply.min(MAX_PLY)].contains(&key) || self.game_keys.contains(&key)
+    }
+
+    /// Searches the good captures until the position is quiet, the side to move
+    /// may also stand pat with the static eval. In check all the evasions are searched
+    /// instead, as standing pat would ignore the threat.
+    pub fn quiescence(&mut self, ply: usize, alpha: i32, beta: i32, state: &mut ChessState) -> i32 {
+        if self.stopped() {
+            return 0;
+        }
+
+        self.visit_node();
+        count!(self.stats.nodes);
+        count!(self.stats.quiescence_nodes);
+
+        // a capture may leave too little material to mate
+        if is_dead_draw(state) {
+            return 0;
+        }
+
+        if ply >= MAX_PLY {
+            return evaluate_relative(state, &self.weights);
+        }
+
+        let in_check = self.move_generator.is_in_check(state);
+
+        let stand_pat = if in_check { -INFINITE_SCORE } else { evaluate_relative(state, &self.weights) };
+
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+
+        let mut alpha = cmp::max(alpha, stand_pat);
+        let mut best_score = stand_pat;
+
+        let mut picker = if in_check { MovePicker::new(None, [None, None]) } else { MovePicker::captures() };
+        let mut move_count = 0usize;
+
+        while let Some(cur_move) = picker.next_move(&self.move_generator, state) {
+            move_count += 1;
+
+            state.do_move(&cur_move);
+            let score = -self.quiescence(ply + 1, -beta, -alpha, state);
+            state.undo_move(&cur_move);
+
+            best_score = cmp::max(best_score, score);
+            alpha = cmp::max(alpha, score);
+
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        if in_check && move_count == 0 && !self.stopped() {
+            // checkmate, the sooner the worse
+            return -MATE_SCORE + ply as i32;
+        }
+
+        best_score
+    }
+
+    /// Negamax alpha-beta search with principal variation search. Scores are relative
+    /// to the side to move, the result may fall outside of the window.
+    /// `extensions` is the number of plies the path may still be extended by.
+    #[allow(clippy::too_many_arguments)]
+    pub fn search(
+        &mut self,
+        ply: usize,
+        depth: u32,
+        alpha: i32,
+        beta: i32,
+        allow_null: bool,
+        extensions: u32,
+        state: &mut ChessState,
+    ) -> i32 {
+        if depth == 0 {
+            // resolve the pending captures before estimating the position
+            return self.quiescence(ply, alpha, beta, state);
+        }
+
+        if self.stopped() {
+            return 0;
+        }
+
+        self.visit_node();
+        count!(self.stats.nodes);
+
+        let key = state.hash_key();
+
+        if self.is_repetition(ply, key) || is_dead_draw(state) {
+            return 0;
+        }
+
+        if ply >= MAX_PLY {
+            return evaluate_relative(state, &self.weights);
+        }
+
+        self.path_keys[ply] = key;
+
+        let params = self.params;
+        let pv_node = beta - alpha > 1;
+
+        let tt_entry = self.tt.probe(key);
+
+        count!(self.stats.tt_probes);
+        if tt_entry.is_some() {
+            count!(self.stats.tt_hits);
+        }
+        let hash_move = tt_entry.and_then(|entry| entry.best_move(state.next_to_move));
+
+        if let Some(entry) = tt_entry.filter(|entry| entry.depth >= depth && !pv_node) {
+            let score = entry.score(ply);
+
+            let cutoff = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+
+            if cutoff {
+                return score;
+            }
+        }
+        let in_check = self.move_generator.is_in_check(state);
+        let static_eval = if in_check { -INFINITE_SCORE } else { evaluate_relative(state, &self.weights) };
+
+        let mut depth = depth;
+        let mut extensions = extensions;
+
+        if in_check && params.one_reply_extensions && extensions > 0 {
+            let mut evasions: Vec<ChessMove> = Vec::new();
+            self.move_generator.generate_moves(state, &mut evasions);
+
+            // a forced reply costs no choice, look one ply further instead
+            if evasions.len() == 1 {
+                depth += 1;
+                extensions -= 1;
+            }
+        }
+
+        // the forward pruning trusts the static eval, which means nothing in check or around mates
+        let can_prune = !in_check && !is_mate_score(alpha) && !is_mate_score(beta);
+
+        if params.reverse_futility_pruning
+            && can_prune
+            && depth <= params.reverse_futility_depth
+            && static_eval - params.reverse_futility_margin * depth as i32 >= beta
+        {
+            return static_eval;
+        }
+
+        // the captures of the quiescence search would miss a quiet check
+        if params.razoring
+            && can_prune
+            && depth <= params.razoring_depth
+            && static_eval + params.razoring_margin * depth as i32 <= alpha
+            && !self.has_checking_move(state)
+        {
+            let score = self.quiescence(ply, alpha, alpha + 1, state);
+
+            if score <= alpha {
+                return score;
+            }
+        }
+
+        if params.null_move
+            && allow_null
+            && !in_check
+            && depth >= params.null_move_min_depth
+            && Self::has_non_pawn_material(state, state.next_to_move)
+            && static_eval >= beta
+            && self.null_move_cutoff(ply, depth, beta, state)
+        {
+            return beta;
+        }
+
+        let futile = params.futility_pruning
+            && can_prune
+            && depth <= params.futility_depth
+            && static_eval + params.futility_margin * depth as i32 <= alpha;
+
+        // the verification search costs a ply of the extension budget like the extension itself
+        let singular_move = match (tt_entry, hash_move.as_ref()) {
+            (Some(entry), Some(candidate))
+                if params.singular_extensions
+                    && extensions > 0
+                    && depth >= params.singular_min_depth
+                    && entry.depth + 3 >= depth
+                    && entry.bound != Bound::Upper
+                    && !is_mate_score(entry.score(ply)) =>
+            {
+                let singular_beta = entry.score(ply) - params.singular_margin * depth as i32;
+
+                if self.is_singular(ply, depth, singular_beta, candidate, extensions - 1, state) {
+                    Some(candidate.clone())
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        let killers = self.killers[ply].clone();
+
+        let mut picker = MovePicker::new(hash_move, killers.clone());
+
+        let original_alpha = alpha;
+        let mut alpha = alpha;
+        let mut best_score = -INFINITE_SCORE;
+        let mut best_move: Option<ChessMove> = None;
+        let mut move_count = 0usize;
+
+        while let Some(cur_move) = picker.next_move(&self.move_generator, state) {
+            move_count += 1;
+
+            let is_quiet = !cur_move.is_capture() && cur_move.promote.is_none();
+
+            state.do_move(&cur_move);
+
+            let gives_check = self.move_generator.is_in_check(state);
+
+            // quiet moves can not make up for the missing material this close to the horizon
+            if futile && move_count > 1 && is_quiet && !gives_check && !Self::is_killer(&killers, &cur_move) {
+                state.undo_move(&cur_move);
+                continue;
+            }
+
+            let singular = singular_move.as_ref().is_some_and(|m| m.is_same(&cur_move));
+
+            let (child_depth, child_extensions) = if (singular || gives_check && params.check_extensions) && extensions > 0 {
+                (depth, extensions - 1)
+            } else {
+                (depth - 1, extensions)
+            };
+
+            let score = if move_count == 1 {
+                -self.search(ply + 1, child_depth, -beta, -alpha, true, child_extensions, state)
+            } else {
+                let reduce = params.late_move_reductions
+                    && depth >= params.lmr_min_depth
+                    && move_count > params.lmr_full_moves
+                    && is_quiet
+                    && !in_check
+                    && !gives_check
+                    && !Self::is_killer(&killers, &cur_move);
+
+                let reduction = if reduce { params.lmr_reduction } else { 0 };
+                let reduced_depth = child_depth.saturating_sub(reduction);
 
-            let dummy_move = ChessMove::new(state.next_to_move, 0, 0, None);
+                // the null window scout only tells if the move could improve alpha
+                let mut score = -self.search(ply + 1, reduced_depth, -alpha - 1, -alpha, true, child_extensions, state);
+
+                if score > alpha && reduction > 0 {
+                    score = -self.search(ply + 1, child_depth, -alpha - 1, -alpha, true, child_extensions, state);
+                }
+
+                if score > alpha && score < beta {
+                    score = -self.search(ply + 1, child_depth, -beta, -alpha, true, child_extensions, state);
+                }
+
+                score
+            };
 
-            let king_hit = self.move_generator.is_king_hit(state, &dummy_move);
+            state.undo_move(&cur_move);
 
-            return if king_hit {
-                // checkmate
-                state.next_to_move.value() * -100000
+            if score > best_score {
+                best_score = score;
+                best_move = Some(cur_move.clone());
+            }
+
+            alpha = cmp::max(alpha, score);
+
+            if alpha >= beta {
+                count!(self.stats.cutoffs);
+                if move_count == 1 {
+                    count!(self.stats.first_move_cutoffs);
+                }
+
+                self.store_killer(ply, &cur_move);
+                break;
+            }
+        }
+
+        if self.stopped() {
+            // the scores of an interrupted search are not to be trusted
+            return 0;
+        }
+
+        if move_count == 0 {
+            return if in_check {
+                // checkmate, the sooner the worse
+                -MATE_SCORE + ply as i32
             } else {
                 // draw
                 0
             };
         }
 
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+
+        self.tt.store(key, ply, depth, best_score, bound, best_move.as_ref());
+
+        best_score
+    }
+
+    /// Searches the root moves to the given depth with principal variation search.
+    /// A move improving the best score is moved to the front of the list.
+    fn search_root(&mut self, depth: u32, alpha: i32, beta: i32, moves: &mut Vec<ChessMove>, state: &mut ChessState) -> i32 {
         let mut alpha = alpha;
-        let mut beta = beta;
+        let mut best_score = -INFINITE_SCORE;
+
+        let params = self.params;
+
+        count!(self.stats.nodes);
 
-        return if state.next_to_move == Side::White {
+        for idx in 0..moves.len() {
+            state.do_move(&moves[idx]);
 
-            for cur_move in moves.iter() {
+            let (child_depth, extensions) = if params.check_extensions && params.extension_budget > 0 && self.move_generator.is_in_check(state) {
+                (depth, params.extension_budget.saturating_sub(1))
+            } else {
+                (depth - 1, params.extension_budget)
+            };
+
+            let score = if idx == 0 {
+                -self.search(1, child_depth, -beta, -alpha, true, extensions, state)
+            } else {
+                let mut score = -self.search(1, child_depth, -alpha - 1, -alpha, true, extensions, state);
+
+                if score > alpha && score < beta {
+                    score = -self.search(1, child_depth, -beta, -alpha, true, extensions, state);
+                }
 
-                state.do_move(cur_move);
+                score
+            };
 
-                let score = self.min_max_search(penalty + 1, depth - 1, alpha, beta, state);
+            state.undo_move(&moves[idx]);
 
-                state.undo_move(cur_move);
+            if self.stopped() {
+                break;
+            }
 
-                alpha = cmp::max(alpha, score);
+            if score > best_score {
+                best_score = score;
 
-                if alpha >= beta {
-                    break
+                if idx > 0 && score > alpha {
+                    let best = moves.remove(idx);
+                    moves.insert(0, best);
                 }
             }
 
-            alpha
-        } else {
+            alpha = cmp::max(alpha, score);
+
+            if alpha >= beta {
+                break;
+            }
+        }
 
-            for cur_move in moves.iter() {
+        best_score
+    }
 
-                state.do_move(cur_move);
-                let score = self.min_max_search(penalty + 1, depth - 1, alpha, beta, state);
-                state.undo_move(cur_move);
+    /// Searches the root with a window around the expected score,
+    /// widening it on the failing side until the score falls inside.
+    fn aspiration_search(&mut self, depth: u32, guess: i32, moves: &mut Vec<ChessMove>, state: &mut ChessState) -> i32 {
+        let mut delta = self.params.aspiration_window;
+        let mut alpha = cmp::max(guess - delta, -INFINITE_SCORE);
+        let mut beta = cmp::min(guess + delta, INFINITE_SCORE);
 
-                beta = cmp::min(beta, score);
+        loop {
+            let score = self.search_root(depth, alpha, beta, moves, state);
 
-                if alpha >= beta {
-                    break
-                }
+            if self.stopped() {
+                return score;
+            }
+
+            delta *= 2;
+
+            if score <= alpha && alpha > -INFINITE_SCORE {
+                alpha = cmp::max(score - delta, -INFINITE_SCORE);
+            } else if score >= beta && beta < INFINITE_SCORE {
+                beta = cmp::min(score + delta, INFINITE_SCORE);
+            } else {
+                return score;
+            }
+        }
+    }
+
+    /// Deepens the search of the root moves iteration by iteration, keeping the best move first.
+    /// Odd helper threads search one ply deeper than the main thread in each iteration,
+    /// so the threads spread over more positions of the shared table.
+    /// The main thread of a limited skill level scores every root move in each iteration instead.
+    /// Returns the score of the last completed iteration.
+    fn iterate(&mut self, moves: &mut Vec<ChessMove>, state: &mut ChessState) -> i32 {
+        let depth_offset = (self.thread_id % 2) as u32;
+        let max_depth = if self.infinite { INFINITE_DEPTH } else { self.params.max_depth };
+        let max_depth = self.skill.max_depth().map_or(max_depth, |depth| depth.min(max_depth));
+
+        let mut score = 0;
+        self.path_keys[0] = state.hash_key();
+
+        for depth in 1 + depth_offset..=max_depth + depth_offset {
+            let start_nodes = self.stats.nodes;
 
+            let iteration_score = if self.thread_id == 0 && self.skill.is_limited() {
+                self.score_root_moves(depth, moves, state)
+            } else if depth >= self.params.aspiration_min_depth {
+                self.aspiration_search(depth, score, moves, state)
+            } else {
+                self.search_root(depth, -INFINITE_SCORE, INFINITE_SCORE, moves, state)
+            };
+
+            if self.stopped() {
+                break;
             }
 
-            beta
+            score = iteration_score;
+            self.stats.iteration_nodes.push(self.stats.nodes - start_nodes);
+
+            if self.thread_id == 0 {
+                info!("depth: {}, score: {}, best move: {}", depth, score, moves[0]);
+                self.search_lines(depth, score, moves, state);
+            }
         }
 
+        score
     }
 
-    pub fn find_best_move(&self, state: &mut ChessState) -> Option<ChessMove> {
+    /// Searches the root moves after the best one for the further lines of a multi-PV search,
+    /// each line taking the best of the moves left, then reports all lines of the iteration.
+    fn search_lines(&mut self, depth: u32, best_score: i32, moves: &mut Vec<ChessMove>, state: &mut ChessState) {
+        let mut scores = vec![best_score];
+
+        for line in 1..self.multi_pv.min(moves.len()) {
+            let mut rest = moves.split_off(line);
+            let score = self.search_root(depth, -INFINITE_SCORE, INFINITE_SCORE, &mut rest, state);
+            moves.append(&mut rest);
+
+            if self.stopped() {
+                // the lines of an unfinished iteration are not reported
+                return;
+            }
+
+            scores.push(score);
+        }
+
+        for (line, score) in scores.into_iter().enumerate() {
+            self.report(depth, line, score, moves, state);
+        }
+    }
+
+    /// Passes a line of the completed iteration to the listener.
+    fn report(&mut self, depth: u32, line: usize, score: i32, moves: &[ChessMove], state: &mut ChessState) {
+        if self.listener.is_none() {
+            return;
+        }
+
+        let iteration = Iteration {
+            depth,
+            line: line + 1,
+            score,
+            nodes: self.node_count,
+            time: self.start.elapsed(),
+            moves: moves.len(),
+            pv: self.principal_variation(&moves[line], depth as usize, state),
+        };
+
+        if let Some(listener) = self.listener.as_mut() {
+            listener(&iteration);
+        }
+    }
+
+    /// Searches every root move with the full window, for the move choice of the limited skill levels.
+    /// When all moves are scored, they are sorted by score and the scores kept in `root_scores`.
+    /// Returns the best score.
+    fn score_root_moves(&mut self, depth: u32, moves: &mut Vec<ChessMove>, state: &mut ChessState) -> i32 {
+        let extensions = self.params.extension_budget;
+        let mut scores = Vec::with_capacity(moves.len());
+
+        count!(self.stats.nodes);
+
+        for m in moves.iter() {
+            state.do_move(m);
+            let score = -self.search(1, depth - 1, -INFINITE_SCORE, INFINITE_SCORE, true, extensions, state);
+            state.undo_move(m);
+
+            if self.stopped() {
+                // the scores of the previous iteration are kept
+                return -INFINITE_SCORE;
+            }
+
+            scores.push(score);
+        }
+
+        let mut scored: Vec<(ChessMove, i32)> = moves.drain(..).zip(scores).collect();
+        scored.sort_by_key(|(_, score)| cmp::Reverse(*score));
+
+        self.root_scores = scored.iter().map(|(_, score)| *score).collect();
+        moves.extend(scored.into_iter().map(|(m, _)| m));
+
+        self.root_scores[0]
+    }
+
+    /// Searches the position with all threads, the main thread deciding the move.
+    pub fn find_best_move(&mut self, state: &mut ChessState) -> Option<ChessMove> {
         let mut moves: Vec<ChessMove> = Vec::new();
 
         self.move_generator.generate_moves(state, &mut moves);
 
-        if moves.len() == 0 {
+        if moves.is_empty() {
             // checkmate or stalemate situation
             return None;
         }
 
-        let mut best_score = 0;
-        let mut best_index = 0usize;
-        let (mut min, mut max) = (0, 0);
+        self.stats = SearchStats::default();
+        self.node_count = 0;
+        self.node_limit = self.skill.node_limit();
+        self.root_scores.clear();
 
-        for (idx, m) in moves.iter().enumerate() {
+        self.start = Instant::now();
+        let helpers: Vec<ChessEngine> = (1..self.threads).map(|id| self.helper(id)).collect();
 
-            state.do_move(m);
+        thread::scope(|scope| {
+            let handles: Vec<_> = helpers
+                .into_iter()
+                .map(|mut helper| {
+                    let mut helper_state = state.clone();
+                    let mut helper_moves = moves.clone();
 
-            let score = self.min_max_search(0, 4, i32::MIN, i32::MAX, state);
+                    scope.spawn(move || {
+                        helper.iterate(&mut helper_moves, &mut helper_state);
+                        helper.stats
+                    })
+                })
+                .collect();
 
-            state.undo_move(m);
+            self.score = self.iterate(&mut moves, state);
+            self.stop.store(true, Ordering::Relaxed);
 
-            if idx == 0 {
-                min = score;
-                max = score;
+            for handle in handles {
+                let helper_stats = handle.join().unwrap();
+                self.stats.merge(&helper_stats);
             }
+        });
 
-            let is_new_best = idx == 0
-                || state.next_to_move == Side::White && score > best_score
-                || state.next_to_move == Side::Black && score < best_score;
+        self.stop.store(false, Ordering::Relaxed);
 
-            if is_new_best {
-                best_score = score;
-                best_index = idx;
+        self.stats.time = self.start.elapsed();
+
+        for line in self.stats.to_string().lines() {
+            info!("{}", line);
+        }
+
+        if self.skill.is_limited() && self.root_scores.len() == moves.len() {
+            // choose among the moves scoring close to the best one
+            let idx = self.skill.pick_move(&self.root_scores, &mut self.rng);
+
+            info!("skill level {}: picked {} of {} moves", self.skill.level(), moves[idx], moves.len());
+
+            return Some(moves[idx].clone());
+        }
+
+        Some(moves[0].clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selective_search() {
+        let positions = [
+            // back rank mates for both sides
+            ("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", "a1a8"),
+            ("r5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1", "a8a1"),
+            // the hanging queen
+            ("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", "d2d5"),
+        ];
+
+        for (null_move, late_move_reductions) in [(false, false), (true, false), (false, true), (true, true)] {
+            let mut engine = ChessEngine::new();
+            engine.params.null_move = null_move;
+            engine.params.late_move_reductions = late_move_reductions;
+
+            for (fen, expected) in positions.iter() {
+                let mut state = ChessState::from_fen(fen);
+                let expected = ChessMove::parse(expected, &state).unwrap();
+                let best = engine.find_best_move(&mut state).unwrap();
+
+                assert!(best.is_same(&expected), "{} {} {} {}", fen, best, null_move, late_move_reductions);
             }
         }
+    }
+
+    #[test]
+    fn test_negamax_scores() {
+        let mut engine = ChessEngine::new();
+        engine.params.null_move = false;
+        engine.params.late_move_reductions = false;
+
+        // mate in one is found one ply after the root move, for both sides
+        for fen in ["6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", "r5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1"].iter() {
+            let mut state = ChessState::from_fen(fen);
+            let mut moves = Vec::new();
+            engine.move_generator.generate_moves(&mut state, &mut moves);
+
+            let score = engine.search_root(3, -INFINITE_SCORE, INFINITE_SCORE, &mut moves, &mut state);
+            assert_eq!(score, MATE_SCORE - 1, "{}", fen);
+        }
 
-        info!("min: {}, max: {}, best score: {}", min, max, best_score);
+        // without selectivity the window only changes the speed, not the score
+        let fens = [
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+        ];
 
-        let next_move = &moves[best_index];
+        for fen in fens.iter() {
+            let mut state = ChessState::from_fen(fen);
+            let mut moves = Vec::new();
+            engine.move_generator.generate_moves(&mut state, &mut moves);
 
-        Some(next_move.clone())
+            let full = engine.search_root(3, -INFINITE_SCORE, INFINITE_SCORE, &mut moves, &mut state);
+
+            for guess in [full - 300, full, full + 20].iter() {
+                engine.params.aspiration_window = 10;
+                assert_eq!(engine.aspiration_search(3, *guess, &mut moves, &mut state), full, "{}", fen);
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_extensions() {
+        // the rook grab with check looks best at one ply, only the extension sees
+        // the bishop grab with check mating instead
+        let fen = "r4rk1/6b1/8/8/q7/3b2R1/5R2/B3K3 w - - 0 1";
+        let rook_grab = ChessMove::parse("f2f8", &ChessState::from_fen(fen)).unwrap();
+
+        let mut engine = ChessEngine::new();
+        engine.params.max_depth = 1;
+
+        let best = engine.find_best_move(&mut ChessState::from_fen(fen)).unwrap();
+        assert!(!best.is_same(&rook_grab), "{}", best);
+
+        engine.params.extension_budget = 0;
+
+        let best = engine.find_best_move(&mut ChessState::from_fen(fen)).unwrap();
+        assert!(best.is_same(&rook_grab), "{}", best);
+    }
+
+    #[test]
+    fn test_singular_moves() {
+        let mut engine = ChessEngine::new();
+
+        // taking the queen back is the only move which does not lose
+        let mut state = ChessState::from_fen("3rk3/8/8/3Q4/8/8/8/4K3 b - - 0 1");
+        let recapture = ChessMove::parse("d8d5", &state).unwrap();
+        assert!(engine.is_singular(0, 4, 0, &recapture, 4, &mut state));
+
+        let mut state = ChessState::new_game();
+        let king_pawn = ChessMove::parse("e2e4", &state).unwrap();
+        assert!(!engine.is_singular(0, 4, -50, &king_pawn, 4, &mut state));
+
+        // a move which can not be played is never singular
+        let mut state = ChessState::from_fen("3rk3/8/8/3Q4/8/8/8/4K3 b - - 0 1");
+        let missing = ChessMove::parse("d8d1", &state).unwrap();
+        assert!(!engine.is_singular(0, 4, 0, &missing, 4, &mut state));
+    }
+
+    #[test]
+    fn test_razoring_checks() {
+        // smothered mate, the queen sacrifice and the knight mate are both quiet looking checks
+        let fen = "5r1k/6pp/7N/3Q4/8/8/8/6K1 w - - 0 1";
+        let sacrifice = ChessMove::parse("d5g8", &ChessState::from_fen(fen)).unwrap();
+
+        let mut engine = ChessEngine::new();
+        engine.params.max_depth = 1;
+
+        let best = engine.find_best_move(&mut ChessState::from_fen(fen)).unwrap();
+        assert!(best.is_same(&sacrifice), "{}", best);
+    }
+
+    #[test]
+    fn test_quiescence_evasions() {
+        // mated on the back rank, standing pat would only count a rook down
+        let mut engine = ChessEngine::new();
+        let mut state = ChessState::from_fen("6k1/5ppp/8/8/8/8/5PPP/r5K1 w - - 0 1");
+
+        assert_eq!(engine.quiescence(0, -INFINITE_SCORE, INFINITE_SCORE, &mut state), -MATE_SCORE);
+
+        // no deeper than the tables of the search, the evaluation stands
+        let eval = evaluate_relative(&state, &engine.weights);
+        assert_eq!(engine.quiescence(MAX_PLY, -INFINITE_SCORE, INFINITE_SCORE, &mut state), eval);
+        assert_eq!(engine.search(MAX_PLY, 2, -INFINITE_SCORE, INFINITE_SCORE, true, 0, &mut state), eval);
+    }
+
+    #[test]
+    fn test_forward_pruning() {
+        let positions = [
+            ("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", "a1a8"),
+            ("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", "d2d5"),
+            // the knight fork wins the queen
+            ("q3k3/8/8/1N6/8/8/8/4K3 w - - 0 1", "b5c7"),
+        ];
+
+        for pruning in 0..4 {
+            let mut engine = ChessEngine::new();
+            engine.params.futility_pruning = pruning == 1;
+            engine.params.reverse_futility_pruning = pruning == 2;
+            engine.params.razoring = pruning == 3;
+
+            for (fen, expected) in positions.iter() {
+                let mut state = ChessState::from_fen(fen);
+                let expected = ChessMove::parse(expected, &state).unwrap();
+                let best = engine.find_best_move(&mut state).unwrap();
+
+                assert!(best.is_same(&expected), "{} {} {}", fen, best, pruning);
+            }
+        }
+
+        assert!(is_mate_score(MATE_SCORE - 3));
+        assert!(is_mate_score(-MATE_SCORE + 10));
+        assert!(!is_mate_score(40000));
+    }
+
+    #[test]
+    fn test_lazy_smp() {
+        let positions = [
+            ("r5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1", "a8a1"),
+            ("q3k3/8/8/1N6/8/8/8/4K3 w - - 0 1", "b5c7"),
+            ("5r1k/6pp/7N/3Q4/8/8/8/6K1 w - - 0 1", "d5g8"),
+        ];
+
+        let mut engine = ChessEngine::new();
+        engine.set_threads(3);
+
+        for (fen, expected) in positions.iter() {
+            let mut state = ChessState::from_fen(fen);
+            let expected = ChessMove::parse(expected, &state).unwrap();
+            let best = engine.find_best_move(&mut state).unwrap();
+
+            assert!(best.is_same(&expected), "{} {}", fen, best);
+        }
+
+        // the threads leave their results in the shared table
+        let mut state = ChessState::from_fen(positions[1].0);
+        let best = engine.find_best_move(&mut state).unwrap();
+        state.do_move(&best);
+        assert!(engine.tt.probe(state.hash_key()).is_some());
+
+        assert_eq!(engine.threads(), 3);
+        engine.set_threads(1000);
+        assert_eq!(engine.threads(), MAX_THREADS);
+    }
+
+    #[test]
+    fn test_infinite_search() {
+        let iterations = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reported = iterations.clone();
+
+        let mut engine = ChessEngine::new();
+        engine.set_listener(Some(Box::new(move |iteration: &Iteration| {
+            reported.lock().unwrap().push(iteration.clone());
+        })));
+
+        let mut state = ChessState::from_fen("q3k3/8/8/1N6/8/8/8/4K3 w - - 0 1");
+        let best = engine.find_best_move(&mut state).unwrap();
+
+        {
+            let iterations = iterations.lock().unwrap();
+            let depths: Vec<u32> = iterations.iter().map(|iteration| iteration.depth).collect();
+            assert_eq!(depths, (1..=engine.params.max_depth).collect::<Vec<u32>>());
+
+            let last = iterations.last().unwrap();
+            assert!(last.pv[0].is_same(&best));
+            assert!(last.pv.len() > 1 && last.pv.len() <= last.depth as usize);
+        }
+
+        // without a depth limit the search goes on until stopped
+        let stop = engine.stop_flag();
+        let stop_depth = engine.params.max_depth + 2;
+        let depths = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reported = depths.clone();
+
+        engine.set_infinite(true);
+        engine.set_listener(Some(Box::new(move |iteration: &Iteration| {
+            reported.lock().unwrap().push(iteration.depth);
+
+            if iteration.depth == stop_depth {
+                stop.store(true, Ordering::Relaxed);
+            }
+        })));
+
+        let mut state = ChessState::new_game();
+        assert!(engine.find_best_move(&mut state).is_some());
+
+        assert_eq!(depths.lock().unwrap().last(), Some(&stop_depth));
+        assert_eq!(state.hash_key(), ChessState::new_game().hash_key());
+    }
+
+    #[test]
+    fn test_multi_pv() {
+        let lines = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reported = lines.clone();
+
+        let mut engine = ChessEngine::new();
+        engine.set_multi_pv(3);
+        engine.set_listener(Some(Box::new(move |iteration: &Iteration| {
+            reported.lock().unwrap().push(iteration.clone());
+        })));
+
+        let mut state = ChessState::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1");
+        let best = engine.find_best_move(&mut state).unwrap();
+        assert!(best.is_same(&ChessMove::parse("a1a8", &state).unwrap()));
+        assert_eq!(engine.score(), MATE_SCORE - 1);
+
+        let lines = lines.lock().unwrap();
+        assert_eq!(lines.len(), 3 * engine.params.max_depth as usize);
+
+        let last: Vec<&Iteration> = lines.iter().filter(|iteration| iteration.depth == engine.params.max_depth).collect();
+        assert_eq!(last.iter().map(|iteration| iteration.line).collect::<Vec<usize>>(), vec![1, 2, 3]);
+        assert_eq!(last[0].score, MATE_SCORE - 1);
+        assert!(last[1].score < last[0].score && last[2].score <= last[1].score);
+
+        // every line starts with a different root move
+        assert!(!last[1].pv[0].is_same(&last[0].pv[0]));
+        assert!(!last[2].pv[0].is_same(&last[0].pv[0]) && !last[2].pv[0].is_same(&last[1].pv[0]));
+
+        assert_eq!(engine.multi_pv(), 3);
+        engine.set_multi_pv(0);
+        assert_eq!(engine.multi_pv(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "search-stats")]
+    fn test_search_stats() {
+        let mut engine = ChessEngine::new();
+        engine.set_threads(2);
+
+        let mut state = ChessState::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        engine.find_best_move(&mut state).unwrap();
+
+        let stats = engine.stats();
+        assert_eq!(stats.iteration_nodes.len(), engine.params.max_depth as usize);
+        assert!(stats.nodes > stats.iteration_nodes.iter().sum::<u64>(), "helper nodes are added");
+        assert!(stats.quiescence_nodes > 0 && stats.quiescence_nodes < stats.nodes);
+        assert!(stats.tt_hits > 0 && stats.tt_hits <= stats.tt_probes);
+        assert!(stats.first_move_cutoffs > 0 && stats.first_move_cutoffs <= stats.cutoffs);
+        assert!(stats.effective_branching_factor().is_some());
+    }
+
+    #[test]
+    fn test_skill_levels() {
+        let play = |level: u32, seed: u64, fen: &str| {
+            let mut engine = ChessEngine::new();
+            engine.set_skill(Skill::new(level));
+            engine.set_seed(seed);
+
+            engine.find_best_move(&mut ChessState::from_fen(fen)).unwrap().to_coord_string()
+        };
+
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+        // reproducible with the same seed, and varied between the seeds
+        assert_eq!(play(0, 1, start), play(0, 1, start));
+        let moves: Vec<String> = (0..10).map(|seed| play(0, seed, start)).collect();
+        assert!(moves.iter().any(|m| *m != moves[0]));
+
+        // the noise is far below a queen
+        assert_eq!(play(0, 2, "4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1"), "d2d5");
+
+        // at the lowest level only the first iteration runs, within the node limit
+        let mut engine = ChessEngine::new();
+        engine.set_skill(Skill::new(0));
+        engine.find_best_move(&mut ChessState::from_fen(start)).unwrap();
+        assert!(engine.stats().iteration_nodes.len() <= 1);
+
+        // the move choice stays within the node budget of the level
+        for level in [0, 4, 9] {
+            let mut engine = ChessEngine::new();
+            engine.set_skill(Skill::new(level));
+            engine.find_best_move(&mut ChessState::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4")).unwrap();
+            assert!(engine.node_count <= Skill::new(level).node_limit().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_draw_detection() {
+        // a rook down, with Ka2 as the only move
+        let fen = "1r5k/7p/8/8/8/8/8/K7 w - - 0 1";
+
+        let mut engine = ChessEngine::new();
+        engine.find_best_move(&mut ChessState::from_fen(fen)).unwrap();
+        assert!(engine.score() < -300);
+
+        // the move returns to a position of the game
+        let mut state = ChessState::from_fen(fen);
+        let mut moves: Vec<ChessMove> = Vec::new();
+        engine.move_generator.generate_moves(&mut state, &mut moves);
+        assert_eq!(moves.len(), 1);
+
+        state.do_move(&moves[0]);
+        engine.set_game_keys(vec![state.hash_key()]);
+        state.undo_move(&moves[0]);
+
+        engine.clear_hash();
+        engine.find_best_move(&mut state).unwrap();
+        assert_eq!(engine.score(), 0);
+
+        // a knight up, with no way to mate
+        let mut engine = ChessEngine::new();
+        engine.find_best_move(&mut ChessState::from_fen("8/8/4k3/8/8/3K4/4N3/8 w - - 0 1")).unwrap();
+        assert_eq!(engine.score(), 0);
+    }
+
+    #[test]
+    fn test_non_pawn_material() {
+        let state = ChessState::from_fen("4k3/pppp4/8/8/8/8/4PPPP/3NK3 w - - 0 1");
+
+        assert!(ChessEngine::has_non_pawn_material(&state, Side::White));
+        assert!(!ChessEngine::has_non_pawn_material(&state, Side::Black));
     }
 }
+
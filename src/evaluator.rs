@@ -4,6 +4,50 @@ use crate::state::*;
 
 type Scores = [i32; 64];
 
+/// Weights of the evaluation terms, adjustable through the engine options.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct EvalWeights {
+    /// material values in centipawns
+    pub pawn: i32,
+    pub knight: i32,
+    pub bishop: i32,
+    pub rook: i32,
+    pub queen: i32,
+    /// scale of the piece-square tables in percent
+    pub positional: i32,
+}
+
+impl EvalWeights {
+    pub const DEFAULT: EvalWeights = EvalWeights {
+        pawn: 100,
+        knight: 320,
+        bishop: 330,
+        rook: 500,
+        queen: 900,
+        positional: 100,
+    };
+
+    /// Material value of the piece, negative for black.
+    fn piece_value(&self, piece: BBPiece) -> i32 {
+        let value = match piece {
+            BBPiece::WPawn | BBPiece::BPawn => self.pawn,
+            BBPiece::WKnight | BBPiece::BKnight => self.knight,
+            BBPiece::WBishop | BBPiece::BBishop => self.bishop,
+            BBPiece::WRook | BBPiece::BRook => self.rook,
+            BBPiece::WQueen | BBPiece::BQueen => self.queen,
+            _ => piece.value().abs(),
+        };
+
+        value * piece.get_side().value()
+    }
+}
+
+impl Default for EvalWeights {
+    fn default() -> Self {
+        EvalWeights::DEFAULT
+    }
+}
+
 static B_PAWN: Scores = [
     0, 0, 0, 0, 0, 0, 0, 0, 50, 50, 50, 50, 50, 50, 50, 50, 10, 10, 20, 30, 30, 20, 10, 10, 5, 5,
     10, 25, 25, 10, 5, 5, 0, 0, 0, 20, 20, 0, 0, 0, 5, -5, -10, 0, 0, -10, -5, 5, 5, 10, 10, -20,
@@ -56,56 +100,28 @@ static W_QUEEN: Scores = flip_scores(&B_QUEEN);
 static W_KING: Scores = flip_scores(&B_KING);
 static W_KING_END: Scores = flip_scores(&B_KING_END);
 
+/// Scores seen from the other side of the board.
 const fn flip_scores(s: &Scores) -> Scores {
-    let result: Scores = [
-        s[63], s[62], s[61], s[60], s[59], s[58], s[57], s[56], s[55], s[54], s[53], s[52], s[51],
-        s[50], s[49], s[48], s[47], s[46], s[45], s[44], s[43], s[42], s[41], s[40], s[39], s[38],
-        s[37], s[36], s[35], s[34], s[33], s[32], s[31], s[30], s[29], s[28], s[27], s[26], s[25],
-        s[24], s[23], s[22], s[21], s[20], s[19], s[18], s[17], s[16], s[15], s[14], s[13], s[12],
-        s[11], s[10], s[19], s[18], s[7], s[6], s[5], s[4], s[3], s[2], s[1], s[0],
-    ];
+    let mut result: Scores = [0; 64];
 
-    result
-}
-
-static INDEX_64: Scores = [
-    63, 0, 58, 1, 59, 47, 53, 2, 60, 39, 48, 27, 54, 33, 42, 3, 61, 51, 37, 40, 49, 18, 28, 20, 55,
-    30, 34, 11, 43, 14, 22, 4, 62, 57, 46, 52, 38, 26, 32, 41, 50, 36, 17, 19, 29, 10, 13, 21, 56,
-    45, 25, 31, 35, 16, 9, 12, 44, 24, 15, 8, 23, 7, 6, 5,
-];
-
-fn bit_scan_forward(bb: u64) -> i32 {
-    use std::num::Wrapping;
+    let mut idx = 0;
+    while idx < 64 {
+        result[idx] = s[63 - idx];
+        idx += 1;
+    }
 
-    let mul = Wrapping(bb) * Wrapping(0x07EDD5E59A4E28C2u64);
-    let idx = (mul.0 >> 58) as usize;
-    return INDEX_64[idx];
+    result
 }
 
 fn position_to_score(scores: &Scores, board: BBoard) -> i32 {
-    use std::num::Wrapping;
-
-    let mut result = 0;
-    let mut bit;
-    let mut board = board;
-
-    while board > 0 {
-        bit = board & (Wrapping(0u64) - Wrapping(board)).0;
-        let idx = bit_scan_forward(bit) as usize;
-
-        result += scores[idx];
-
-        board ^= bit;
-    }
-
-    result
+    Bitboard(board).into_iter().map(|square| scores[square.idx()]).sum()
 }
 
 impl Demo for Scores {
     fn demo(&self) {
         for y in 0..8 {
             for x in 0..8 {
-                let v = self[(7 - y) * 8 + x];
+                let v = self[Square::new(File(x), Rank(7 - y)).idx()];
                 print!("{:3} ", v);
             }
             println!();
@@ -114,7 +130,8 @@ impl Demo for Scores {
     }
 }
 
-pub fn evaluate_position(state: &ChessState) -> i32 {
+pub fn evaluate_position(state: &ChessState, weights: &EvalWeights) -> i32 {
+    let mut material = 0i32;
     let mut w = 0i32;
     let mut b = 0i32;
     let mut total = 0i32;
@@ -124,7 +141,7 @@ pub fn evaluate_position(state: &ChessState) -> i32 {
         
         let ones = bboard.count_ones() as i32;
 
-        w += ones * piece.value();
+        material += ones * weights.piece_value(*piece);
         total += ones * piece.value().abs();
 
     }
@@ -151,7 +168,13 @@ pub fn evaluate_position(state: &ChessState) -> i32 {
         w += position_to_score(&W_KING, state.bboard(BBPiece::WKing));
     }
 
-    w - b
+    material + (w - b) * weights.positional / 100
+}
+
+/// Score of the position for the side to move.
+#[inline]
+pub fn evaluate_relative(state: &ChessState, weights: &EvalWeights) -> i32 {
+    state.next_to_move.value() * evaluate_position(state, weights)
 }
 
 
@@ -162,22 +185,61 @@ mod tests {
 
     #[test]
     fn test_bit_scan_forward() {
-        for i in 0u64..63 {
-            let bb: BBoard = 1u64 << i;
-            assert_eq!(bit_scan_forward(bb), i as i32);
+        for i in 0..63 {
+            let square = Square::from_idx(i);
+            assert_eq!(Bitboard(square.bit()).first(), Some(square));
+        }
+    }
+
+    #[test]
+    fn test_flip_scores() {
+        let tables = [
+            (&W_PAWN, &B_PAWN),
+            (&W_KNIGHT, &B_KNIGHT),
+            (&W_BISHOP, &B_BISHOP),
+            (&W_ROOK, &B_ROOK),
+            (&W_QUEEN, &B_QUEEN),
+            (&W_KING, &B_KING),
+            (&W_KING_END, &B_KING_END),
+        ];
+
+        // the white tables are the black ones seen from the other side
+        for (white, black) in tables.iter() {
+            for (idx, score) in white.iter().enumerate() {
+                assert_eq!(*score, black[63 - idx]);
+            }
         }
+
+        // the entries next to the corner, once taken from the wrong rank
+        assert_eq!(W_KNIGHT[54], B_KNIGHT[9]);
+        assert_eq!(W_KNIGHT[55], B_KNIGHT[8]);
+    }
+
+    #[test]
+    fn test_eval_weights() {
+        // white is a knight up, its pieces on their home squares
+        let state = ChessState::from_fen("4k3/8/8/8/8/8/8/1N2K3 w - - 0 1");
+        let weights = EvalWeights::default();
+
+        let positional = position_to_score(&W_KNIGHT, state.bboard(BBPiece::WKnight))
+            + position_to_score(&W_KING, state.bboard(BBPiece::WKing))
+            - position_to_score(&B_KING, state.bboard(BBPiece::BKing));
+        assert_eq!(evaluate_position(&state, &weights), 320 + positional);
+
+        let weights = EvalWeights { knight: 300, positional: 0, ..weights };
+        assert_eq!(evaluate_position(&state, &weights), 300);
+        assert_eq!(evaluate_relative(&state.clone(), &weights), 300);
     }
 
     #[test]
     fn test_position_to_score() {
-        for i in 0u64..63 {
-            let bb: BBoard = 1u64 << i;
-            let result = position_to_score(&W_PAWN, bb);
+        for (i, score) in W_PAWN.iter().enumerate() {
+            let result = position_to_score(&W_PAWN, Square::from_idx(i).bit());
 
-            assert_eq!(result, W_PAWN[i as usize]);
+            assert_eq!(result, *score);
         }
 
-        let bb: BBoard = 1 << 15 | 1 << 7;
+        let bb = Square::from_idx(15).bit() | Square::from_idx(7).bit();
         let result = position_to_score(&W_PAWN, bb);
 
         assert_eq!(result, W_PAWN[15] + W_PAWN[7]);
@@ -1,9 +1,13 @@
 
+use std::fmt;
+use std::fmt::Formatter;
 use std::num::Wrapping;
 use std::result::Result;
 
+use crate::adjudication::Adjudicator;
 use crate::bboard::{BBoard, bb_print, bb_to_coord};
 use crate::engine::ChessEngine;
+use crate::skill::{Skill, MAX_ELO, MAX_SKILL};
 use crate::state::{ChessState, BBPiece, Side};
 
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
@@ -24,13 +28,6 @@ impl ChessCoord {
         ChessCoord { x, y }
     }
 
-    pub fn to_string(&self) -> String {
-        let mut result = String::with_capacity(2);
-        result.push((self.x + b'a') as char);
-        result.push((self.y + b'1') as char);
-
-        result
-    }
 
     #[inline]
     pub fn as_bboard(&self) -> BBoard {
@@ -57,6 +54,12 @@ impl ChessCoord {
     }
 }
 
+impl fmt::Display for ChessCoord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", (self.x + b'a') as char, (self.y + b'1') as char)
+    }
+}
+
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct ChessMove {
     pub deltas: Vec<(BBPiece, BBoard)>,
@@ -68,26 +71,51 @@ pub struct ChessMove {
 
 impl ChessMove {
     pub fn new(side: Side, move_from: BBoard, move_to: BBoard, promote: Option<BBPiece>) -> ChessMove {
-        return ChessMove {
-            deltas: Vec::new(),            
+        ChessMove {
+            deltas: Vec::new(),
             side,
             move_from,
             move_to,
             promote,
-        };
+        }
     }
 
     pub fn get_piece(&self) -> BBPiece {
 
         for (piece, delta) in self.deltas.iter() {
             if delta & self.move_from > 0 {
-                return piece.clone();
+                return *piece;
             }
         }
 
         panic!("Unknown moving piece!");
     }
 
+    /// Returns true if both moves have the same coordinates and promotion piece,
+    /// regardless of the deltas they carry.
+    #[inline]
+    pub fn is_same(&self, other: &ChessMove) -> bool {
+        self.move_from == other.move_from
+            && self.move_to == other.move_to
+            && self.promote.map(|p| p.to_char().to_ascii_lowercase())
+                == other.promote.map(|p| p.to_char().to_ascii_lowercase())
+    }
+
+    /// Returns the opponent piece removed from the board by this move, if any.
+    pub fn captured_piece(&self) -> Option<BBPiece> {
+        let other_side = self.side.opposite();
+
+        self.deltas
+            .iter()
+            .map(|(piece, _)| *piece)
+            .find(|piece| piece.get_side() == other_side && BBPiece::get_pieces().contains(piece))
+    }
+
+    #[inline]
+    pub fn is_capture(&self) -> bool {
+        self.captured_piece().is_some()
+    }
+
     pub fn add_delta(&mut self, piece: BBPiece, delta: BBoard) {
         if delta == 0u64 {
             return;
@@ -171,14 +199,9 @@ impl ChessMove {
         let from_str = &move_str.as_bytes()[0..2];
         let to_str = &move_str.as_bytes()[2..4];
 
-        let promo_string = &move_str.as_bytes().get(4);
+        let promo_string = move_str.as_bytes().get(4);
 
-        let promote = 
-            if let Some(promo_char) = promo_string {
-                Some(BBPiece::from_byte(promo_char))
-            } else {
-                None
-            };
+        let promote = promo_string.map(BBPiece::from_byte);
 
         let move_from = ChessCoord::from_string(from_str);
         let move_to = ChessCoord::from_string(to_str);
@@ -264,17 +287,15 @@ impl ChessMove {
         Ok(result)
     }
 
-    pub fn to_string(&self) -> String {
-        let mut result = String::with_capacity(4);
+    /// Coordinate notation used by xboard and UCI, like `e2e4` or `e7e8q`.
+    pub fn to_coord_string(&self) -> String {
+        let mut result = String::with_capacity(5);
 
-        result.push_str(self.get_piece().to_string().as_str());
-        result.push_str(" ");
         result.push_str(bb_to_coord(self.move_from).as_str());
-        result.push_str("-");
         result.push_str(bb_to_coord(self.move_to).as_str());
-        
+
         if let Some(promoted) = self.promote {
-            result.push(promoted.to_char());
+            result.push(promoted.to_char().to_ascii_lowercase());
         }
 
         result
@@ -282,19 +303,39 @@ impl ChessMove {
 
     pub fn demo(&self) {
 
-        println!("Move: {}", self.to_string());
+        println!("Move: {}", self);
         println!("deltas:");
         for (piece, board) in self.deltas.iter() {
-            println!("{}", piece.to_string());
+            println!("{}", piece);
             bb_print(*board);
         }
 
     }
 }
 
+impl fmt::Display for ChessMove {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}-{}",
+            self.get_piece(),
+            bb_to_coord(self.move_from),
+            bb_to_coord(self.move_to)
+        )?;
+
+        if let Some(promoted) = self.promote {
+            write!(f, "{}", promoted.to_char())?;
+        }
+
+        Ok(())
+    }
+}
+
 pub struct GameSetup {
     pub xboard: bool,
     pub pondering: bool,
+    /// xboard analyze mode, searching the position until it changes
+    pub analyzing: bool,
 
     pub computer_player: [bool; 2],
 
@@ -305,24 +346,117 @@ pub struct GameSetup {
     pub forced: bool,
 
     pub game_state: ChessState,
+    /// moves played since the game start or the last `setboard`, for `undo`
+    pub history: Vec<ChessMove>,
 
     pub engine: ChessEngine,
+
+    /// move found by pondering, for the position with the given hash key
+    pub ponder_result: Option<(u64, ChessMove)>,
+
+    /// when to resign, offer and accept draws
+    pub adjudicator: Adjudicator,
+
+    /// strength settings, the skill level applies unless the strength is limited by the Elo
+    pub skill_level: u32,
+    pub limit_strength: bool,
+    pub elo: u32,
 }
 
 impl GameSetup {
     pub fn new() -> GameSetup {
-        let result = GameSetup {
+        GameSetup {
             xboard: false,
             pondering: false,
+            analyzing: false,
             computer_player: [false, false],
             time: 0,
             otime: 0,
             moves_left: 0,
             forced: false,
             game_state: ChessState::new_game(),
+            history: Vec::new(),
             engine: ChessEngine::new(),
+            ponder_result: None,
+            adjudicator: Adjudicator::new(),
+            skill_level: MAX_SKILL,
+            limit_strength: false,
+            elo: MAX_ELO,
+        }
+    }
+
+    /// Plays a move on the board, keeping it for `undo`.
+    pub fn play_move(&mut self, chess_move: ChessMove) {
+        self.game_state.do_move(&chess_move);
+        self.history.push(chess_move);
+    }
+
+    /// Takes back the last move, returns false when there is none.
+    pub fn undo_move(&mut self) -> bool {
+        match self.history.pop() {
+            Some(chess_move) => {
+                self.game_state.undo_move(&chess_move);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Hash keys of the positions of the game before the current one, oldest first.
+    pub fn history_keys(&self) -> Vec<u64> {
+        let mut state = self.game_state.clone();
+
+        let mut keys: Vec<u64> = self
+            .history
+            .iter()
+            .rev()
+            .map(|chess_move| {
+                state.undo_move(chess_move);
+                state.hash_key()
+            })
+            .collect();
+
+        keys.reverse();
+        keys
+    }
+
+    /// How often the current position has occurred in the game, counting itself.
+    pub fn repetitions(&self) -> usize {
+        let key = self.game_state.hash_key();
+
+        1 + self.history_keys().iter().filter(|history_key| **history_key == key).count()
+    }
+
+    /// Passes the strength settings to the engine.
+    pub fn update_skill(&mut self) {
+        let skill = if self.limit_strength {
+            Skill::from_elo(self.elo)
+        } else {
+            Skill::new(self.skill_level)
         };
 
-        result
+        self.engine.set_skill(skill);
+    }
+}
+
+impl Default for GameSetup {
+    fn default() -> Self {
+        GameSetup::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coord_string() {
+        // castling, promotion and a plain move, as sent in the xboard `move` reply
+        let state = ChessState::from_fen("4k3/1P6/8/8/8/8/8/4K2R w K - 0 1");
+
+        for coords in ["e1g1", "b7b8q", "h1h7"].iter() {
+            let chess_move = ChessMove::parse(coords, &state).unwrap();
+            assert_eq!(chess_move.to_coord_string(), *coords);
+        }
     }
 }
@@ -0,0 +1,64 @@
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use simplelog::{CombinedLogger, Config, LevelFilter, WriteLogger};
+
+/// log file used until the `Log File` option changes it
+pub const DEFAULT_LOG_FILE: &str = "chess.log";
+
+/// File the log lines go to, none when logging to a file is switched off.
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+/// Writes the log lines to the current log file, so the file can be changed at runtime.
+struct LogFile;
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match LOG_FILE.lock().unwrap().as_mut() {
+            Some(file) => file.write(buf),
+            None => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match LOG_FILE.lock().unwrap().as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Starts logging at the info level to the default log file.
+pub fn init() {
+    CombinedLogger::init(vec![WriteLogger::new(LevelFilter::Trace, Config::default(), LogFile)]).unwrap();
+
+    log::set_max_level(LevelFilter::Info);
+
+    if let Err(msg) = set_file(DEFAULT_LOG_FILE) {
+        eprintln!("{}", msg);
+    }
+}
+
+/// Sets the most detailed level logged, by its name: `off`, `error`, `warn`, `info`, `debug` or `trace`.
+pub fn set_level(level: &str) -> Result<(), String> {
+    let level = LevelFilter::from_str(level).map_err(|_| format!("unknown log level: {}", level))?;
+    log::set_max_level(level);
+
+    Ok(())
+}
+
+/// Continues the log in a new file, an empty path stops writing the log.
+pub fn set_file(path: &str) -> Result<(), String> {
+    let file = if path.is_empty() {
+        None
+    } else {
+        Some(File::create(path).map_err(|e| format!("can not create log file {}: {}", path, e))?)
+    };
+
+    *LOG_FILE.lock().unwrap() = file;
+
+    Ok(())
+}
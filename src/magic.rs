@@ -1,325 +1,363 @@
-use std::num::Wrapping;
-use std::rc::Rc;
+use crate::bboard::BBoard;
+use crate::piece_moves::PieceMoveProvider;
+use crate::slider_attacks::SliderAttacks;
 
-use arr_macro::arr;
+#[rustfmt::skip]
+pub(crate) const ROOK_POP_BITS: [u32; 64] = [
+    12, 11, 11, 11, 11, 11, 11, 12,
+    11, 10, 10, 10, 10, 10, 10, 11,
+    11, 10, 10, 10, 10, 10, 10, 11,
+    11, 10, 10, 10, 10, 10, 10, 11,
+    11, 10, 10, 10, 10, 10, 10, 11,
+    11, 10, 10, 10, 10, 10, 10, 11,
+    11, 10, 10, 10, 10, 10, 10, 11,
+    12, 11, 11, 11, 11, 11, 11, 12
+];
 
-use crate::bboard::{bb_coord_q, bb_get_q, BBoard, last_bit};
-use crate::piece_moves::PieceMoveProvider;
+#[rustfmt::skip]
+pub(crate) const BISHOP_POP_BITS: [u32; 64] = [
+    6, 5, 5, 5, 5, 5, 5, 6,
+    5, 5, 5, 5, 5, 5, 5, 5,
+    5, 5, 7, 7, 7, 7, 5, 5,
+    5, 5, 7, 9, 9, 7, 5, 5,
+    5, 5, 7, 9, 9, 7, 5, 5,
+    5, 5, 7, 7, 7, 7, 5, 5,
+    5, 5, 5, 5, 5, 5, 5, 5,
+    6, 5, 5, 5, 5, 5, 5, 6,
+];
 
-pub struct Magic {
-    move_provider: Rc<PieceMoveProvider>,
-    rook_pop_bits: [i32; 64],
-    bishop_pop_bits: [i32; 64],
-    rook_shift_bits: [i32; 64],
-    bishop_shift_bits: [i32; 64],
-    rook_magic: [u64; 64],
-    bishop_magic: [u64; 64],
-    rook_attack_bits: [Vec<BBoard>; 64],
-    bishop_attack_bits: [Vec<BBoard>; 64],
+pub(crate) const ROOK_MAGIC: [u64; 64] = [
+    0xA8002C000108020u64,
+    0x4440200140003000u64,
+    0x8080200010011880u64,
+    0x380180080141000u64,
+    0x1A00060008211044u64,
+    0x410001000A0C0008u64,
+    0x9500060004008100u64,
+    0x100024284A20700u64,
+    0x802140008000u64,
+    0x80C01002A00840u64,
+    0x402004282011020u64,
+    0x9862000820420050u64,
+    0x1001448011100u64,
+    0x6432800200800400u64,
+    0x40100010002000Cu64,
+    0x2800D0010C080u64,
+    0x90C0008000803042u64,
+    0x4010004000200041u64,
+    0x3010010200040u64,
+    0xA40828028001000u64,
+    0x123010008000430u64,
+    0x24008004020080u64,
+    0x60040001104802u64,
+    0x582200028400D1u64,
+    0x4000802080044000u64,
+    0x408208200420308u64,
+    0x610038080102000u64,
+    0x3601000900100020u64,
+    0x80080040180u64,
+    0xC2020080040080u64,
+    0x80084400100102u64,
+    0x4022408200014401u64,
+    0x40052040800082u64,
+    0xB08200280804000u64,
+    0x8A80A008801000u64,
+    0x4000480080801000u64,
+    0x911808800801401u64,
+    0x822A003002001894u64,
+    0x401068091400108Au64,
+    0x4A10A00004Cu64,
+    0x2000800640008024u64,
+    0x1486408102020020u64,
+    0x100A000D50041u64,
+    0x810050020B0020u64,
+    0x204000800808004u64,
+    0x20048100A000Cu64,
+    0x112000831020004u64,
+    0x9000040810002u64,
+    0x440490200208200u64,
+    0x8910401000200040u64,
+    0x6404200050008480u64,
+    0x4B824A2010010100u64,
+    0x4080801810C0080u64,
+    0x400802A0080u64,
+    0x8224080110026400u64,
+    0x40002C4104088200u64,
+    0x1002100104A0282u64,
+    0x1208400811048021u64,
+    0x3201014A40D02001u64,
+    0x5100019200501u64,
+    0x101000208001005u64,
+    0x2008450080702u64,
+    0x1002080301D00Cu64,
+    0x410201CE5C030092u64,
+];
+
+pub(crate) const BISHOP_MAGIC: [u64; 64] = [
+    0x40210414004040u64,
+    0x2290100115012200u64,
+    0xA240400A6004201u64,
+    0x80A0420800480u64,
+    0x4022021000000061u64,
+    0x31012010200000u64,
+    0x4404421051080068u64,
+    0x1040882015000u64,
+    0x8048C01206021210u64,
+    0x222091024088820u64,
+    0x4328110102020200u64,
+    0x901CC41052000D0u64,
+    0xA828C20210000200u64,
+    0x308419004A004E0u64,
+    0x4000840404860881u64,
+    0x800008424020680u64,
+    0x28100040100204A1u64,
+    0x82001002080510u64,
+    0x9008103000204010u64,
+    0x141820040C00B000u64,
+    0x81010090402022u64,
+    0x14400480602000u64,
+    0x8A008048443C00u64,
+    0x280202060220u64,
+    0x3520100860841100u64,
+    0x9810083C02080100u64,
+    0x41003000620C0140u64,
+    0x6100400104010A0u64,
+    0x20840000802008u64,
+    0x40050A010900A080u64,
+    0x818404001041602u64,
+    0x8040604006010400u64,
+    0x1028044001041800u64,
+    0x80B00828108200u64,
+    0xC000280C04080220u64,
+    0x3010020080880081u64,
+    0x10004C0400004100u64,
+    0x3010020200002080u64,
+    0x202304019004020Au64,
+    0x4208A0000E110u64,
+    0x108018410006000u64,
+    0x202210120440800u64,
+    0x100850C828001000u64,
+    0x1401024204800800u64,
+    0x41028800402u64,
+    0x20642300480600u64,
+    0x20410200800202u64,
+    0xCA02480845000080u64,
+    0x140C404A0080410u64,
+    0x2180A40108884441u64,
+    0x4410420104980302u64,
+    0x1108040046080000u64,
+    0x8141029012020008u64,
+    0x894081818082800u64,
+    0x40020404628000u64,
+    0x804100C010C2122u64,
+    0x8168210510101200u64,
+    0x1088148121080u64,
+    0x204010100C11010u64,
+    0x1814102013841400u64,
+    0xC00010020602u64,
+    0x1045220C040820u64,
+    0x12400808070840u64,
+    0x2004012A040132u64,
+];
+
+pub(crate) const ROOK_DIRECTIONS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+pub(crate) const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(1, 1), (-1, 1), (1, -1), (-1, -1)];
+
+const ROOK_TABLE_SIZE: usize = table_size(&ROOK_POP_BITS);
+const BISHOP_TABLE_SIZE: usize = table_size(&BISHOP_POP_BITS);
+
+/// source of the inner attack masks of the tables
+const PIECE_MOVES: PieceMoveProvider = PieceMoveProvider::new();
+
+/// Attack tables computed at compile time.
+static ROOK_TABLE: SliderTable<ROOK_TABLE_SIZE> = SliderTable::new(
+    &PIECE_MOVES.inner_rook_attack_bits,
+    &ROOK_MAGIC,
+    &ROOK_POP_BITS,
+    &ROOK_DIRECTIONS,
+);
+static BISHOP_TABLE: SliderTable<BISHOP_TABLE_SIZE> = SliderTable::new(
+    &PIECE_MOVES.inner_bishop_attack_bits,
+    &BISHOP_MAGIC,
+    &BISHOP_POP_BITS,
+    &BISHOP_DIRECTIONS,
+);
+
+static MAGIC: Magic = Magic {
+    rook: &ROOK_TABLE,
+    bishop: &BISHOP_TABLE,
+};
+
+/// Magic bitboard lookup of one slider type, the attacks of all squares
+/// are kept in one array at the square offsets.
+struct SliderTable<const N: usize> {
+    inner_bits: [BBoard; 64],
+    magic: [u64; 64],
+    shift_bits: [u32; 64],
+    offsets: [usize; 64],
+    attacks: [BBoard; N],
 }
 
-impl Magic {
-    pub fn new(move_provider: Rc<PieceMoveProvider>) -> Magic {
-        let mut result = Magic {
-            move_provider,
-            rook_pop_bits: [
-                12, 11, 11, 11, 11, 11, 11, 12,
-                11, 10, 10, 10, 10, 10, 10, 11,
-                11, 10, 10, 10, 10, 10, 10, 11,
-                11, 10, 10, 10, 10, 10, 10, 11,
-                11, 10, 10, 10, 10, 10, 10, 11,
-                11, 10, 10, 10, 10, 10, 10, 11,
-                11, 10, 10, 10, 10, 10, 10, 11,
-                12, 11, 11, 11, 11, 11, 11, 12
-            ],
-            bishop_pop_bits: [
-                6, 5, 5, 5, 5, 5, 5, 6,
-                5, 5, 5, 5, 5, 5, 5, 5,
-                5, 5, 7, 7, 7, 7, 5, 5,
-                5, 5, 7, 9, 9, 7, 5, 5,
-                5, 5, 7, 9, 9, 7, 5, 5,
-                5, 5, 7, 7, 7, 7, 5, 5,
-                5, 5, 5, 5, 5, 5, 5, 5,
-                6, 5, 5, 5, 5, 5, 5, 6,
-            ],
-            rook_shift_bits: [0; 64],
-            bishop_shift_bits: [0; 64],
-            rook_magic: [
-                0xA8002C000108020u64,
-                0x4440200140003000u64,
-                0x8080200010011880u64,
-                0x380180080141000u64,
-                0x1A00060008211044u64,
-                0x410001000A0C0008u64,
-                0x9500060004008100u64,
-                0x100024284A20700u64,
-                0x802140008000u64,
-                0x80C01002A00840u64,
-                0x402004282011020u64,
-                0x9862000820420050u64,
-                0x1001448011100u64,
-                0x6432800200800400u64,
-                0x40100010002000Cu64,
-                0x2800D0010C080u64,
-                0x90C0008000803042u64,
-                0x4010004000200041u64,
-                0x3010010200040u64,
-                0xA40828028001000u64,
-                0x123010008000430u64,
-                0x24008004020080u64,
-                0x60040001104802u64,
-                0x582200028400D1u64,
-                0x4000802080044000u64,
-                0x408208200420308u64,
-                0x610038080102000u64,
-                0x3601000900100020u64,
-                0x80080040180u64,
-                0xC2020080040080u64,
-                0x80084400100102u64,
-                0x4022408200014401u64,
-                0x40052040800082u64,
-                0xB08200280804000u64,
-                0x8A80A008801000u64,
-                0x4000480080801000u64,
-                0x911808800801401u64,
-                0x822A003002001894u64,
-                0x401068091400108Au64,
-                0x4A10A00004Cu64,
-                0x2000800640008024u64,
-                0x1486408102020020u64,
-                0x100A000D50041u64,
-                0x810050020B0020u64,
-                0x204000800808004u64,
-                0x20048100A000Cu64,
-                0x112000831020004u64,
-                0x9000040810002u64,
-                0x440490200208200u64,
-                0x8910401000200040u64,
-                0x6404200050008480u64,
-                0x4B824A2010010100u64,
-                0x4080801810C0080u64,
-                0x400802A0080u64,
-                0x8224080110026400u64,
-                0x40002C4104088200u64,
-                0x1002100104A0282u64,
-                0x1208400811048021u64,
-                0x3201014A40D02001u64,
-                0x5100019200501u64,
-                0x101000208001005u64,
-                0x2008450080702u64,
-                0x1002080301D00Cu64,
-                0x410201CE5C030092u64,
-            ],
-            bishop_magic: [
-                0x40210414004040u64,
-                0x2290100115012200u64,
-                0xA240400A6004201u64,
-                0x80A0420800480u64,
-                0x4022021000000061u64,
-                0x31012010200000u64,
-                0x4404421051080068u64,
-                0x1040882015000u64,
-                0x8048C01206021210u64,
-                0x222091024088820u64,
-                0x4328110102020200u64,
-                0x901CC41052000D0u64,
-                0xA828C20210000200u64,
-                0x308419004A004E0u64,
-                0x4000840404860881u64,
-                0x800008424020680u64,
-                0x28100040100204A1u64,
-                0x82001002080510u64,
-                0x9008103000204010u64,
-                0x141820040C00B000u64,
-                0x81010090402022u64,
-                0x14400480602000u64,
-                0x8A008048443C00u64,
-                0x280202060220u64,
-                0x3520100860841100u64,
-                0x9810083C02080100u64,
-                0x41003000620C0140u64,
-                0x6100400104010A0u64,
-                0x20840000802008u64,
-                0x40050A010900A080u64,
-                0x818404001041602u64,
-                0x8040604006010400u64,
-                0x1028044001041800u64,
-                0x80B00828108200u64,
-                0xC000280C04080220u64,
-                0x3010020080880081u64,
-                0x10004C0400004100u64,
-                0x3010020200002080u64,
-                0x202304019004020Au64,
-                0x4208A0000E110u64,
-                0x108018410006000u64,
-                0x202210120440800u64,
-                0x100850C828001000u64,
-                0x1401024204800800u64,
-                0x41028800402u64,
-                0x20642300480600u64,
-                0x20410200800202u64,
-                0xCA02480845000080u64,
-                0x140C404A0080410u64,
-                0x2180A40108884441u64,
-                0x4410420104980302u64,
-                0x1108040046080000u64,
-                0x8141029012020008u64,
-                0x894081818082800u64,
-                0x40020404628000u64,
-                0x804100C010C2122u64,
-                0x8168210510101200u64,
-                0x1088148121080u64,
-                0x204010100C11010u64,
-                0x1814102013841400u64,
-                0xC00010020602u64,
-                0x1045220C040820u64,
-                0x12400808070840u64,
-                0x2004012A040132u64,
-            ],
-            rook_attack_bits: arr![Vec::new(); 64],
-            bishop_attack_bits: arr![Vec::new(); 64],
+impl<const N: usize> SliderTable<N> {
+    /// Table of the slider moving in the given directions, `inner_bits` masking the squares
+    /// whose occupancy blocks it.
+    const fn new(
+        inner_bits: &[BBoard; 64],
+        magic: &[u64; 64],
+        pop_bits: &[u32; 64],
+        directions: &[(i32, i32); 4],
+    ) -> SliderTable<N> {
+        let mut result = SliderTable {
+            inner_bits: *inner_bits,
+            magic: *magic,
+            shift_bits: [0; 64],
+            offsets: [0; 64],
+            attacks: [0; N],
         };
 
-        for i in 0..64 {
-            result.rook_shift_bits[i] = 64 - result.rook_pop_bits[i];
-            result.bishop_shift_bits[i] = 64 - result.bishop_pop_bits[i];
-        }
+        let mut offset = 0usize;
+
+        let mut idx = 0usize;
+        while idx < 64 {
+            result.shift_bits[idx] = 64 - pop_bits[idx];
+            result.offsets[idx] = offset;
+
+            // every subset of the inner bits
+            let mut field = 0u64;
+            loop {
+                let index = (field.wrapping_mul(magic[idx]) >> result.shift_bits[idx]) as usize;
+                result.attacks[offset + index] = slider_attacks(idx, field, directions);
+
+                field = field.wrapping_sub(inner_bits[idx]) & inner_bits[idx];
+                if field == 0 {
+                    break;
+                }
+            }
 
-        result.init_magic();
+            offset += 1 << pop_bits[idx];
+            idx += 1;
+        }
 
         result
     }
 
     #[inline]
-    pub fn get_bishop_attack_bits(&self, idx: usize, board: BBoard) -> BBoard {
-        let attack_bits = board & (self.move_provider.inner_bishop_attack_bits[idx]);
-        let index = (Wrapping(attack_bits) * Wrapping(self.bishop_magic[idx]))
-            >> (self.bishop_shift_bits[idx] as usize);
+    fn attacks(&self, idx: usize, board: BBoard) -> BBoard {
+        let attack_bits = board & self.inner_bits[idx];
+        let index = attack_bits.wrapping_mul(self.magic[idx]) >> self.shift_bits[idx];
 
-        self.bishop_attack_bits[idx][index.0 as usize]
+        self.attacks[self.offsets[idx] + index as usize]
     }
+}
 
-    #[inline]
-    pub fn get_rook_attack_bits(&self, idx: usize, board: BBoard) -> BBoard {
-        let attack_bits = board & self.move_provider.inner_rook_attack_bits[idx];
-        let index = Wrapping(attack_bits) * Wrapping(self.rook_magic[idx])
-            >> (self.rook_shift_bits[idx] as usize);
+const fn table_size(pop_bits: &[u32; 64]) -> usize {
+    let mut result = 0;
 
-        self.rook_attack_bits[idx][index.0 as usize]
+    let mut idx = 0;
+    while idx < 64 {
+        result += 1 << pop_bits[idx];
+        idx += 1;
     }
 
-    fn init_magic(&mut self) {
-        init_rq_magic(
-            &self.rook_shift_bits,
-            &self.move_provider.inner_rook_attack_bits,
-            &self.rook_magic,
-            &mut self.rook_attack_bits,
-            &compute_rook_attack_bits,
-        );
-
-        init_rq_magic(
-            &self.bishop_shift_bits,
-            &self.move_provider.inner_bishop_attack_bits,
-            &self.bishop_magic,
-            &mut self.bishop_attack_bits,
-            &compute_bishop_attack_bits,
-        );
-    }
+    result
 }
 
-fn init_rq_magic(
-    shift_bits: &[i32; 64],
-    inner_attack_bits: &[BBoard; 64],
-    magic: &[u64; 64],
-    result_attack_bits: &mut [Vec<BBoard>; 64],
-    attack_function: &dyn Fn(i32, i32, BBoard) -> BBoard,
-) {
-    // init attack bits
-    for y in 0i32..8 {
-        for x in 0i32..8 {
-            let idx: usize = (y * 8 + x) as usize;
-            let rp = 1 << (64 - shift_bits[idx as usize]);
-
-            result_attack_bits[idx].reserve(rp as usize);
-            for _ in 0..rp {
-                result_attack_bits[idx].push(0u64);
-            }
+/// Attacks of a slider on the given square, walking each direction up to the first blocker.
+pub(crate) const fn slider_attacks(idx: usize, field: BBoard, directions: &[(i32, i32); 4]) -> BBoard {
+    let mut attack: BBoard = 0u64;
 
-            for i in 0..rp {
-                let mut j = i;
-                let mut attack_bits: BBoard = inner_attack_bits[idx];
-                let mut field: BBoard = 0u64;
+    let mut dir = 0;
+    while dir < 4 {
+        let (dx, dy) = directions[dir];
+        let mut x = (idx % 8) as i32 + dx;
+        let mut y = (idx / 8) as i32 + dy;
 
-                while attack_bits > 0 {
-                    let last_bit = last_bit(attack_bits);
+        while x >= 0 && x < 8 && y >= 0 && y < 8 {
+            let bit = 1u64 << (y * 8 + x);
+            attack |= bit;
 
-                    if j & 1 > 0 {
-                        field |= last_bit;
-                    }
+            if field & bit > 0 {
+                break;
+            }
 
-                    j >>= 1;
+            x += dx;
+            y += dy;
+        }
 
-                    attack_bits ^= last_bit;
-                }
+        dir += 1;
+    }
 
-                field &= inner_attack_bits[idx];
-                let mut index = (Wrapping(field) * Wrapping(magic[idx])).0;
-                index >>= shift_bits[idx];
+    attack
+}
 
-                result_attack_bits[idx][index as usize] = attack_function(x, y, field);
-            }
-        }
-    }
+pub struct Magic {
+    rook: &'static SliderTable<ROOK_TABLE_SIZE>,
+    bishop: &'static SliderTable<BISHOP_TABLE_SIZE>,
 }
 
-fn compute_rook_attack_bits(x: i32, y: i32, field: BBoard) -> BBoard {
-    let (mut dir1, mut dir2, mut dir3, mut dir4) = (true, true, true, true);
-    let mut attack: BBoard = 0u64;
-    for k in 1..8 {
-        if dir1 {
-            attack |= bb_coord_q(x + k, y);
-        }
-        if dir2 {
-            attack |= bb_coord_q(x - k, y);
-        }
-        if dir3 {
-            attack |= bb_coord_q(x, y + k);
-        }
-        if dir4 {
-            attack |= bb_coord_q(x, y - k);
-        }
+impl Magic {
+    /// Process-wide magic tables, computed at compile time and shared by all threads.
+    pub fn shared() -> &'static Magic {
+        &MAGIC
+    }
+}
 
-        dir1 = dir1 && !bb_get_q(field, x + k, y);
-        dir2 = dir2 && !bb_get_q(field, x - k, y);
-        dir3 = dir3 && !bb_get_q(field, x, y + k);
-        dir4 = dir4 && !bb_get_q(field, x, y - k);
+impl SliderAttacks for Magic {
+    #[inline]
+    fn get_bishop_attack_bits(&self, idx: usize, board: BBoard) -> BBoard {
+        self.bishop.attacks(idx, board)
     }
 
-    attack
+    #[inline]
+    fn get_rook_attack_bits(&self, idx: usize, board: BBoard) -> BBoard {
+        self.rook.attacks(idx, board)
+    }
 }
 
-fn compute_bishop_attack_bits(x: i32, y: i32, field: BBoard) -> BBoard {
-    let (mut dir1, mut dir2, mut dir3, mut dir4) = (true, true, true, true);
-    let mut attack: BBoard = 0u64;
-    for k in 1..8 {
-        if dir1 {
-            attack |= bb_coord_q(x + k, y + k);
-        }
-        if dir2 {
-            attack |= bb_coord_q(x - k, y + k);
-        }
-        if dir3 {
-            attack |= bb_coord_q(x + k, y - k);
-        }
-        if dir4 {
-            attack |= bb_coord_q(x - k, y - k);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// FNV-1a over the attacks of every occupancy subset of every square
+    fn attacks_snapshot(inner: &[BBoard; 64], attacks: &dyn Fn(usize, BBoard) -> BBoard) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+
+        for (idx, mask) in inner.iter().enumerate() {
+            let mut subset = 0u64;
+
+            loop {
+                hash = (hash ^ attacks(idx, subset)).wrapping_mul(0x0100_0000_01b3);
+
+                subset = subset.wrapping_sub(*mask) & mask;
+                if subset == 0 {
+                    break;
+                }
+            }
         }
 
-        dir1 = dir1 && !bb_get_q(field, x + k, y + k);
-        dir2 = dir2 && !bb_get_q(field, x - k, y + k);
-        dir3 = dir3 && !bb_get_q(field, x + k, y - k);
-        dir4 = dir4 && !bb_get_q(field, x - k, y - k);
+        hash
     }
 
-    attack
+    #[test]
+    fn test_attack_tables() {
+        let magic = Magic::shared();
+        let provider = PieceMoveProvider::shared();
+
+        let rook = attacks_snapshot(&provider.inner_rook_attack_bits, &|idx, occ| magic.get_rook_attack_bits(idx, occ));
+        let bishop =
+            attacks_snapshot(&provider.inner_bishop_attack_bits, &|idx, occ| magic.get_bishop_attack_bits(idx, occ));
+
+        // every occupancy must map to the attacks of a plain ray walk
+        let rook_expected = attacks_snapshot(&provider.inner_rook_attack_bits, &|idx, occ| {
+            slider_attacks(idx, occ, &ROOK_DIRECTIONS)
+        });
+        let bishop_expected = attacks_snapshot(&provider.inner_bishop_attack_bits, &|idx, occ| {
+            slider_attacks(idx, occ, &BISHOP_DIRECTIONS)
+        });
+
+        assert_eq!(rook, rook_expected);
+        assert_eq!(bishop, bishop_expected);
+        assert_eq!(rook, 0x208ecd4560a5de25);
+        assert_eq!(bishop, 0xf20c38629aeaec87);
+    }
 }
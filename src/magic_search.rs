@@ -0,0 +1,286 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::bboard::BBoard;
+use crate::magic::{
+    slider_attacks, BISHOP_DIRECTIONS, BISHOP_MAGIC, BISHOP_POP_BITS, ROOK_DIRECTIONS, ROOK_MAGIC, ROOK_POP_BITS,
+};
+use crate::piece_moves::PieceMoveProvider;
+
+/// Slider type the magic numbers are searched for
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Slider {
+    Rook,
+    Bishop,
+}
+
+impl Slider {
+    fn name(self) -> &'static str {
+        match self {
+            Slider::Rook => "ROOK",
+            Slider::Bishop => "BISHOP",
+        }
+    }
+
+    fn inner_bits(self, idx: usize) -> BBoard {
+        let provider = PieceMoveProvider::shared();
+
+        match self {
+            Slider::Rook => provider.inner_rook_attack_bits[idx],
+            Slider::Bishop => provider.inner_bishop_attack_bits[idx],
+        }
+    }
+
+    fn attacks(self, idx: usize, field: BBoard) -> BBoard {
+        match self {
+            Slider::Rook => slider_attacks(idx, field, &ROOK_DIRECTIONS),
+            Slider::Bishop => slider_attacks(idx, field, &BISHOP_DIRECTIONS),
+        }
+    }
+
+    fn magic(self) -> &'static [u64; 64] {
+        match self {
+            Slider::Rook => &ROOK_MAGIC,
+            Slider::Bishop => &BISHOP_MAGIC,
+        }
+    }
+
+    fn pop_bits(self) -> &'static [u32; 64] {
+        match self {
+            Slider::Rook => &ROOK_POP_BITS,
+            Slider::Bishop => &BISHOP_POP_BITS,
+        }
+    }
+}
+
+/// All occupancies of the inner bits of a square, with the attacks for each of them.
+pub struct Occupancies {
+    fields: Vec<BBoard>,
+    attacks: Vec<BBoard>,
+}
+
+impl Occupancies {
+    pub fn new(slider: Slider, idx: usize) -> Occupancies {
+        let mask = slider.inner_bits(idx);
+
+        let mut result = Occupancies {
+            fields: Vec::with_capacity(1 << mask.count_ones()),
+            attacks: Vec::with_capacity(1 << mask.count_ones()),
+        };
+
+        let mut field = 0u64;
+
+        loop {
+            result.fields.push(field);
+            result.attacks.push(slider.attacks(idx, field));
+
+            field = field.wrapping_sub(mask) & mask;
+            if field == 0 {
+                break;
+            }
+        }
+
+        result
+    }
+
+    /// Checks that the magic gives an index of the given bits for every occupancy,
+    /// where occupancies sharing an index have the same attacks.
+    pub fn verify(&self, magic: u64, bits: u32, table: &mut Vec<BBoard>) -> bool {
+        table.clear();
+        table.resize(1 << bits, 0);
+
+        for (field, attacks) in self.fields.iter().zip(self.attacks.iter()) {
+            let index = (field.wrapping_mul(magic) >> (64 - bits)) as usize;
+
+            // slider attacks are never empty, so zero marks a free entry
+            if table[index] == 0 {
+                table[index] = *attacks;
+            } else if table[index] != *attacks {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Tries random sparse numbers until one verifies for the given table bits.
+pub fn find_magic(slider: Slider, idx: usize, bits: u32, tries: u64, rng: &mut StdRng) -> Option<u64> {
+    let occupancies = Occupancies::new(slider, idx);
+    let mask = slider.inner_bits(idx);
+    let mut table = Vec::new();
+
+    for _ in 0..tries {
+        let magic = rng.gen::<u64>() & rng.gen::<u64>() & rng.gen::<u64>();
+
+        // the high bits of the product make the index, too few of them never work
+        if (mask.wrapping_mul(magic) & 0xff00_0000_0000_0000).count_ones() < 6 {
+            continue;
+        }
+
+        if occupancies.verify(magic, bits, &mut table) {
+            return Some(magic);
+        }
+    }
+
+    None
+}
+
+fn format_table<T: std::fmt::Display>(name: &str, kind: &str, values: &[T]) -> String {
+    let mut result = format!("const {}: [{}; 64] = [\n", name, kind);
+
+    for value in values.iter() {
+        result.push_str(format!("    {},\n", value).as_str());
+    }
+
+    result.push_str("];\n");
+    result
+}
+
+const USAGE: &str = "usage: chessbot magic [--verify] [--rook|--bishop] [--square N] [--shrink N] [--tries N] [--seed N]";
+
+/// Runs the `magic` command line subcommand, which checks the built-in magic numbers
+/// or searches new ones, optionally with `--shrink` fewer index bits than the current tables.
+pub fn run_command(args: &[String]) -> Result<(), String> {
+    let mut sliders = vec![Slider::Rook, Slider::Bishop];
+    let mut squares: Vec<usize> = (0..64).collect();
+    let mut shrink = 0u32;
+    let mut tries = 10_000_000u64;
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    let mut verify_only = false;
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--verify" => verify_only = true,
+            "--rook" => sliders = vec![Slider::Rook],
+            "--bishop" => sliders = vec![Slider::Bishop],
+            "--square" => {
+                let value = args.next().ok_or("missing value for --square")?;
+                let square = value.parse().ok().filter(|s| *s < 64).ok_or(format!("invalid square: {}", value))?;
+                squares = vec![square];
+            }
+            "--shrink" => {
+                let value = args.next().ok_or("missing value for --shrink")?;
+                shrink = value.parse().map_err(|_| format!("invalid bit count: {}", value))?;
+            }
+            "--tries" => {
+                let value = args.next().ok_or("missing value for --tries")?;
+                tries = value.parse().map_err(|_| format!("invalid try count: {}", value))?;
+            }
+            "--seed" => {
+                let value = args.next().ok_or("missing value for --seed")?;
+                seed = value.parse().map_err(|_| format!("invalid seed: {}", value))?;
+            }
+            _ => return Err(format!("unknown magic argument: {}\n{}", arg, USAGE)),
+        }
+    }
+
+    // an index needs at least one bit, the shift by 64 - bits overflows otherwise
+    if !verify_only {
+        for slider in sliders.iter().cloned() {
+            let fewest = squares.iter().map(|idx| slider.pop_bits()[*idx]).min().unwrap_or(0);
+
+            if shrink >= fewest {
+                let allowed = fewest - 1;
+                return Err(format!("--shrink {} leaves no index bits, {} allows at most {}", shrink, slider.name(), allowed));
+            }
+        }
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut failed = 0;
+
+    for slider in sliders {
+        let mut magic = *slider.magic();
+        let mut pop_bits = *slider.pop_bits();
+        let mut table = Vec::new();
+
+        for idx in squares.iter().cloned() {
+            if verify_only {
+                if !Occupancies::new(slider, idx).verify(magic[idx], pop_bits[idx], &mut table) {
+                    println!("{} square {}: magic {:#x} has collisions", slider.name(), idx, magic[idx]);
+                    failed += 1;
+                }
+
+                continue;
+            }
+
+            let bits = pop_bits[idx].saturating_sub(shrink);
+
+            match find_magic(slider, idx, bits, tries, &mut rng) {
+                Some(found) => {
+                    println!("{} square {}: {} bits, magic {:#x}", slider.name(), idx, bits, found);
+                    magic[idx] = found;
+                    pop_bits[idx] = bits;
+                }
+                None => {
+                    println!("{} square {}: no magic for {} bits, keeping the current one", slider.name(), idx, bits);
+                    failed += 1;
+                }
+            }
+        }
+
+        if !verify_only {
+            let size: usize = pop_bits.iter().map(|bits| 1usize << bits).sum();
+            let magic: Vec<String> = magic.iter().map(|m| format!("{:#X}u64", m).replace("0X", "0x")).collect();
+
+            println!();
+            println!("// {} table entries", size);
+            print!("{}", format_table(format!("{}_POP_BITS", slider.name()).as_str(), "u32", &pop_bits));
+            print!("{}", format_table(format!("{}_MAGIC", slider.name()).as_str(), "u64", &magic));
+            println!();
+        }
+    }
+
+    if verify_only && failed == 0 {
+        println!("All magic numbers verified");
+    }
+
+    if verify_only && failed > 0 {
+        Err(format!("{} magic numbers failed", failed))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_magics() {
+        let mut table = Vec::new();
+
+        for slider in [Slider::Rook, Slider::Bishop].iter().cloned() {
+            for idx in 0..64 {
+                let occupancies = Occupancies::new(slider, idx);
+
+                assert_eq!(occupancies.fields.len(), 1 << slider.inner_bits(idx).count_ones());
+                assert!(occupancies.verify(slider.magic()[idx], slider.pop_bits()[idx], &mut table));
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_magic() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut table = Vec::new();
+
+        let magic = find_magic(Slider::Bishop, 27, 9, 1_000_000, &mut rng).unwrap();
+        assert!(Occupancies::new(Slider::Bishop, 27).verify(magic, 9, &mut table));
+
+        // every occupancy at the same index
+        assert!(!Occupancies::new(Slider::Rook, 0).verify(0, 12, &mut table));
+    }
+
+    #[test]
+    fn test_shrink_too_far() {
+        let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        // the corner bishop has 6 index bits
+        assert!(run_command(&args(&["--bishop", "--square", "0", "--shrink", "6"])).is_err());
+        assert!(run_command(&args(&["--rook", "--shrink", "64"])).is_err());
+    }
+}
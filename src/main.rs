@@ -2,38 +2,67 @@
 extern crate log;
 extern crate simplelog;
 
-use std::fs::File;
-
-use simplelog::{CombinedLogger, Config, LevelFilter, WriteLogger};
+use std::sync::{Arc, Mutex};
 
 use state::*;
 
+use crate::engine::Iteration;
 use crate::game_setup::{ChessMove, GameSetup};
 use crate::messaging::{get_message, send_message};
+use crate::options::OPTIONS;
+use crate::ponder::InputAction;
 
+pub mod adjudication;
 pub mod bboard;
 pub mod debug;
 pub mod engine;
 pub mod evaluator;
 pub mod game_setup;
+pub mod logging;
 pub mod magic;
+pub mod magic_search;
 pub mod messaging;
 pub mod move_generator;
+pub mod move_picker;
+pub mod options;
+pub mod perft;
 pub mod piece_moves;
+pub mod ponder;
+pub mod search_stats;
+pub mod skill;
+pub mod slider_attacks;
 pub mod state;
+pub mod tt;
+pub mod uci;
+pub mod zobrist;
 
 fn main() {
-    CombinedLogger::init(vec![WriteLogger::new(
-        LevelFilter::Info,
-        Config::default(),
-        File::create("chess.log").unwrap(),
-    )])
-    .unwrap();
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.len() > 1 {
+        let result = match args[1].as_str() {
+            "perft" => perft::run_command(&args[2..]),
+            "magic" => magic_search::run_command(&args[2..]),
+            _ => Err(format!("unknown command: {}", args[1])),
+        };
+
+        if let Err(msg) = result {
+            eprintln!("{}", msg);
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    logging::init();
 
     let mut setup = GameSetup::new();
 
+    // command which interrupted the pondering or the analysis, handled before reading new ones
+    let mut pending: Option<(String, String)> = None;
+
     loop {
-        let input = get_message();
+        let input = pending.take().unwrap_or_else(get_message);
         let command = input.0.as_str();
         let argument = input.1.as_str();
 
@@ -85,12 +114,48 @@ fn main() {
                 send_message("feature setboard=1");
                 get_message();
 
+                send_message("feature smp=1");
+                get_message();
+
+                send_message("feature memory=1");
+                get_message();
+
+                for option in OPTIONS {
+                    send_message(option.xboard_feature().as_str());
+                    get_message();
+                }
+
                 send_message("feature done=1");
                 get_message();
 
                 send_message("");
             }
 
+            "uci" => {
+                uci::run(&mut setup);
+                return;
+            }
+
+            "cores" => {
+                match argument.parse() {
+                    Ok(cores) => setup.engine.set_threads(cores),
+                    Err(_) => warn!("# invalid core count: {}", argument),
+                }
+            }
+
+            "memory" => {
+                match argument.parse() {
+                    Ok(size) => setup.engine.set_hash_size(size),
+                    Err(_) => warn!("# invalid memory size: {}", argument),
+                }
+            }
+
+            "option" => {
+                if let Err(msg) = set_option(&mut setup, argument) {
+                    warn!("# {}", msg);
+                }
+            }
+
             "hard" => {
                 setup.pondering = true;
             }
@@ -101,6 +166,9 @@ fn main() {
 
             "new" => {
                 setup.game_state = ChessState::new_game();
+                setup.history.clear();
+                setup.adjudicator.reset();
+                setup.engine.clear_hash();
                 setup.computer_player[0] = false;
                 setup.computer_player[1] = false;
                 setup.forced = false;
@@ -108,8 +176,36 @@ fn main() {
 
             "setboard" => {
                 setup.game_state = ChessState::from_fen(argument);
+                setup.history.clear();
+                setup.adjudicator.reset();
             }
 
+            "undo" => {
+                if !setup.undo_move() {
+                    warn!("# no move to undo");
+                }
+            }
+
+            "remove" => {
+                // takes back a move of each side, the same player moves next
+                if !(setup.undo_move() && setup.undo_move()) {
+                    warn!("# no moves to remove");
+                }
+            }
+
+            "analyze" => {
+                setup.analyzing = true;
+            }
+
+            "exit" => {
+                setup.analyzing = false;
+                setup.computer_player = [false, false];
+                setup.forced = true;
+            }
+
+            // the status is only sent during the analysis
+            "." => {}
+
             "force" => {
                 // stop computer from making new moves
                 setup.computer_player[0] = false;
@@ -118,23 +214,50 @@ fn main() {
             }
 
             "time" => {
-                setup.time = argument.parse().unwrap();
+                if let Ok(time) = argument.parse() {
+                    setup.time = time;
+                }
             }
 
             "otim" => {
-                setup.time = argument.parse().unwrap();
+                if let Ok(time) = argument.parse() {
+                    setup.otime = time;
+                }
             }
 
             "quit" => {
                 return;
             }
 
+            "stats" => {
+                // debug output of the last search
+                for line in setup.engine.stats().to_string().lines() {
+                    send_message(format!("# {}", line).as_str());
+                }
+            }
+
             "ping" => {
                 send_message(format!("pong {}", argument).as_str());
             }
 
+            "draw" => {
+                // a draw is accepted by offering one back
+                if setup.adjudicator.accept_draw(&setup.game_state) {
+                    send_message("offer draw");
+                }
+            }
+
+            "result" => {
+                setup.forced = true;
+            }
+
             "usermove" => {
-                let user_move = ChessMove::parse(argument, &setup.game_state);
+                let user_move = ChessMove::parse(argument, &setup.game_state).and_then(|m| {
+                    setup
+                        .engine
+                        .legal_move(&mut setup.game_state, &m)
+                        .ok_or_else(|| format!("illegal move {}", argument))
+                });
 
                 match user_move {
                     Ok(user_move) => {
@@ -142,7 +265,7 @@ fn main() {
                         setup
                             .computer_player[setup.game_state.next_to_move.idx()] = false;
 
-                        setup.game_state.do_move(&user_move);
+                        setup.play_move(user_move.clone());
 
                         // now computer moves as the opposite color
                         setup
@@ -187,6 +310,11 @@ fn main() {
             return;
         }
 
+        if setup.analyzing {
+            pending = analyze(&mut setup);
+            continue;
+        }
+
         if setup.forced {
             continue;
         }
@@ -198,7 +326,23 @@ fn main() {
             continue;
         }
 
-        let next_move = setup.engine.find_best_move(&mut setup.game_state);
+        if claim_draw(&mut setup) {
+            continue;
+        }
+
+        let next_move = match setup.ponder_result.take() {
+            Some((key, ponder_move)) if key == setup.game_state.hash_key() => {
+                info!("ponder hit");
+                Some(ponder_move)
+            }
+            _ => {
+                setup.engine.set_game_keys(setup.history_keys());
+                let best_move = setup.engine.find_best_move(&mut setup.game_state);
+                setup.adjudicator.record_score(setup.engine.score());
+
+                best_move
+            }
+        };
 
         if next_move.is_none() {
             setup.forced = true;
@@ -208,14 +352,180 @@ fn main() {
 
         let next_move = next_move.unwrap();
 
+        let score = setup.engine.score();
+
+        if setup.adjudicator.should_resign() {
+            info!("resigning with score {}", score);
+            send_message("resign");
+            setup.forced = true;
+            continue;
+        }
+
         info!(
             "computer moves as {:?}: {:?}",
             setup.game_state.next_to_move, next_move
         );
 
-        send_message(format!("move {}", next_move.to_string()).as_str());
+        let coords = next_move.to_coord_string();
+        setup.play_move(next_move);
+
+        // the search scores a repetition as a draw, so a move repeating the position
+        // without a better score heads for one: offer it
+        if setup.adjudicator.offer_draws && score <= 0 && setup.repetitions() == 2 {
+            send_message("offer draw");
+        }
 
-        setup.game_state.do_move(&next_move);
+        send_message(format!("move {}", coords).as_str());
         info!("new board state {}", setup.game_state.to_fen());
+
+        if claim_draw(&mut setup) {
+            continue;
+        }
+
+        if setup.pondering {
+            pending = ponder(&mut setup);
+        }
+    }
+}
+
+/// Claims the draw when the position occurred for the third time or neither side can mate,
+/// the game then ends.
+fn claim_draw(setup: &mut GameSetup) -> bool {
+    let reason = if setup.repetitions() >= 3 {
+        "Draw by repetition"
+    } else if adjudication::is_dead_draw(&setup.game_state) {
+        "Insufficient material"
+    } else {
+        return false;
+    };
+
+    send_message(format!("1/2-1/2 {{{}}}", reason).as_str());
+    setup.forced = true;
+
+    true
+}
+
+/// Applies an xboard `option NAME=VALUE` command.
+fn set_option(setup: &mut GameSetup, argument: &str) -> Result<(), String> {
+    let (name, value) = argument.split_once('=').ok_or_else(|| format!("invalid option: {}", argument))?;
+
+    options::set_option(setup, name, value)
+}
+
+/// Thinking output line of xboard: depth, score, time in centiseconds, nodes and the best line.
+fn thinking_line(iteration: &Iteration) -> String {
+    let pv: Vec<String> = iteration.pv.iter().map(|m| m.to_coord_string()).collect();
+
+    format!(
+        "{} {} {} {} {}",
+        iteration.depth,
+        iteration.score,
+        iteration.time.as_millis() / 10,
+        iteration.nodes,
+        pv.join(" ")
+    )
+}
+
+/// Analysis status line of xboard, answering the `.` command.
+fn status_line(iteration: &Iteration) -> String {
+    format!(
+        "stat01: {} {} {} 0 {} {}",
+        iteration.time.as_millis() / 10,
+        iteration.nodes,
+        iteration.depth,
+        iteration.moves,
+        iteration.pv[0].to_coord_string()
+    )
+}
+
+/// Searches the current position without a depth limit, sending the thinking output
+/// of every iteration. Any command changing the position or leaving the analyze mode
+/// stops the search and is returned, the analysis then restarts on the new position.
+fn analyze(setup: &mut GameSetup) -> Option<(String, String)> {
+    let last: Arc<Mutex<Option<Iteration>>> = Arc::new(Mutex::new(None));
+    let reported = last.clone();
+
+    setup.engine.set_game_keys(setup.history_keys());
+    setup.engine.set_infinite(true);
+    setup.engine.set_listener(Some(Box::new(move |iteration| {
+        send_message(thinking_line(iteration).as_str());
+
+        if iteration.line == 1 {
+            *reported.lock().unwrap() = Some(iteration.clone());
+        }
+    })));
+
+    let background = ponder::search_in_background(&mut setup.engine, &setup.game_state, true, |command, argument| {
+        match command {
+            "." => {
+                match last.lock().unwrap().as_ref() {
+                    Some(iteration) => send_message(status_line(iteration).as_str()),
+                    None => send_message("stat01: 0 0 0 0 0"),
+                }
+                InputAction::Ignore
+            }
+            "ping" => {
+                send_message(format!("pong {}", argument).as_str());
+                InputAction::Ignore
+            }
+            _ => InputAction::Abort,
+        }
+    });
+
+    setup.engine.set_infinite(false);
+    setup.engine.set_listener(None);
+
+    background.pending
+}
+
+/// Searches the position after the expected reply while the opponent is thinking.
+/// When the opponent plays it, the search result is kept for the position and the move
+/// is returned to be handled as usual. Any other command stops the pondering and is returned.
+fn ponder(setup: &mut GameSetup) -> Option<(String, String)> {
+    let (expected, ponder_state) = ponder::ponder_position(&setup.engine, &setup.game_state)?;
+    let expected = expected.to_coord_string();
+
+    info!("pondering on {}", expected);
+
+    // the current position comes before the pondered one
+    let mut keys = setup.history_keys();
+    keys.push(setup.game_state.hash_key());
+    setup.engine.set_game_keys(keys);
+
+    let mut clock: Vec<(String, String)> = Vec::new();
+
+    let background = ponder::search_in_background(&mut setup.engine, &ponder_state, true, |command, argument| {
+        match command {
+            "usermove" if argument == expected => InputAction::Finish,
+            "time" | "otim" => {
+                clock.push((command.to_owned(), argument.to_owned()));
+                InputAction::Ignore
+            }
+            "ping" => {
+                send_message(format!("pong {}", argument).as_str());
+                InputAction::Ignore
+            }
+            "hard" => InputAction::Ignore,
+            _ => InputAction::Abort,
+        }
+    });
+
+    for (command, argument) in clock {
+        if let Ok(time) = argument.parse() {
+            match command.as_str() {
+                "time" => setup.time = time,
+                _ => setup.otime = time,
+            }
+        }
     }
+
+    if background.pending.is_some() {
+        info!("ponder miss");
+        return background.pending;
+    }
+
+    setup.adjudicator.record_score(setup.engine.score());
+    setup.ponder_result = background.best_move.map(|m| (ponder_state.hash_key(), m));
+
+    Some(("usermove".to_owned(), expected))
 }
@@ -1,11 +1,40 @@
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
 use log::info;
 
-pub fn get_message() -> (String, String) {
-    use std::io;
+/// Lines read from the standard input by a background thread, so commands
+/// can also be received while the engine is searching.
+static INPUT: OnceLock<Mutex<Receiver<String>>> = OnceLock::new();
+
+fn input() -> &'static Mutex<Receiver<String>> {
+    INPUT.get_or_init(|| {
+        let (sender, receiver) = channel();
+
+        thread::spawn(move || {
+            let stdin = std::io::stdin();
 
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).unwrap();
+            loop {
+                let mut line = String::new();
+
+                match stdin.read_line(&mut line) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        Mutex::new(receiver)
+    })
+}
 
+fn split_message(input: &str) -> (String, String) {
     let input = input.trim().to_owned();
 
     info!("<== received: {}", input);
@@ -18,7 +47,24 @@ pub fn get_message() -> (String, String) {
 
         (s1, s2)
     } else {
-        return (input, "".to_owned());
+        (input, "".to_owned())
+    }
+}
+
+/// Waits for the next command, the end of the input reads as `quit`.
+pub fn get_message() -> (String, String) {
+    match input().lock().unwrap().recv() {
+        Ok(line) => split_message(&line),
+        Err(_) => ("quit".to_owned(), "".to_owned()),
+    }
+}
+
+/// Returns the next command if one arrives within the timeout.
+pub fn poll_message(timeout: Duration) -> Option<(String, String)> {
+    match input().lock().unwrap().recv_timeout(timeout) {
+        Ok(line) => Some(split_message(&line)),
+        Err(RecvTimeoutError::Timeout) => None,
+        Err(RecvTimeoutError::Disconnected) => Some(("quit".to_owned(), "".to_owned())),
     }
 }
 
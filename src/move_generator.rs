@@ -1,27 +1,62 @@
-use std::num::Wrapping;
-use std::rc::Rc;
+use std::cmp;
 
 use crate::bboard::*;
 use crate::debug::*;
 use crate::game_setup::*;
-use crate::magic::Magic;
 use crate::piece_moves::*;
+use crate::slider_attacks::{SliderAttacks, Sliders};
 use crate::state::*;
 use crate::state::{ChessState, BBPiece, Side};
 
+/// first and last ranks, where pawns get promoted
+const PROMOTION_RANKS: BBoard = Rank::R1.mask().0 | Rank::R8.mask().0;
+
+/// second and seventh ranks, where pawns may advance by two squares
+const PAWN_START_RANKS: BBoard = Rank::R2.mask().0 | Rank::R7.mask().0;
+
+/// fourth and fifth ranks, where the two square pawn advances end
+const DOUBLE_PUSH_RANKS: BBoard = Rank::R4.mask().0 | Rank::R5.mask().0;
+
+/// Selects the subset of moves produced by the move generator
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum GenType {
+    All,
+    Captures,
+    Quiets,
+}
+
+/// Precomputed data to tell if a move of the side to move gives check,
+/// see `MoveGenerator::check_info`.
+#[derive(Debug, Clone)]
+pub struct CheckInfo {
+    /// opponent king square index
+    pub king_idx: usize,
+    /// squares from which each piece type checks the opponent king,
+    /// indexed like the white pieces: king, pawn, rook, knight, bishop, queen
+    pub check_squares: [BBoard; 6],
+    /// own pieces which uncover a check from an own slider when moved off the line
+    pub discovered_candidates: BBoard,
+}
+
+/// Move generator over the shared move tables, cheap to create and to share between threads.
+#[derive(Copy, Clone)]
 pub struct MoveGenerator {
-    move_provider: Rc<PieceMoveProvider>,
-    magic: Magic,
+    move_provider: &'static PieceMoveProvider,
+    sliders: &'static Sliders,
+}
+
+impl Default for MoveGenerator {
+    fn default() -> Self {
+        MoveGenerator::new()
+    }
 }
 
 impl MoveGenerator {
     /// Create new move generator
     pub fn new() -> MoveGenerator {
-        let move_provider = Rc::new(PieceMoveProvider::new());
-
         MoveGenerator {
-            move_provider: move_provider.clone(),
-            magic: Magic::new(move_provider.clone()),
+            move_provider: PieceMoveProvider::shared(),
+            sliders: Sliders::shared(),
         }
     }
 
@@ -32,8 +67,6 @@ impl MoveGenerator {
         move_from: BBoard,
         move_candidates: BBoard,
     ) {
-        let mut move_candidates = move_candidates;
-
         let (this_ofs, other_ofs) = state.next_to_move.offsets();
 
         let (this_pawn, other_pawn) = if state.next_to_move == Side::White {
@@ -60,13 +93,12 @@ impl MoveGenerator {
         let other_all_bb = state.bboard_ofs(BBPiece::WAll, other_ofs);
         let all_bb = this_all_bb | other_all_bb;
 
-        while move_candidates > 0 {
-
-            let move_to = move_candidates & (-Wrapping(move_candidates)).0;
+        for to in Bitboard(move_candidates) {
+            let move_to = to.bit();
 
             let mut new_move = ChessMove::new(state.next_to_move, move_from, move_to, None);
 
-            let move_delta = move_from | (move_to & 0x00ffffffffffff00u64);
+            let move_delta = move_from | (move_to & !PROMOTION_RANKS);
 
             new_move.add_delta(this_pawn, move_delta);
             new_move.add_delta(this_all, move_from | move_to);
@@ -95,26 +127,25 @@ impl MoveGenerator {
             }
 
             // create new en-passant
-            if (move_from & 0x00ff00000000ff00u64) > 0u64 && (move_to & 0x000000ffff000000u64) > 0u64 {
+            if move_from & PAWN_START_RANKS > 0 && move_to & DOUBLE_PUSH_RANKS > 0 {
                 let this_en_passant_bb = ((move_to << 8) | (move_to >> 8)) & ((move_from << 8) | (move_from >> 8));
 
                 if this_en_passant_bb & all_bb == 0 {
                     new_move.add_delta(this_en_passant, this_en_passant_bb);
                 } else {
                     // en-passant move is blocked, hence continue without adding it
-                    move_candidates ^= move_to;
                     continue;
                 }
             }
 
             // pawn captures
-            if other_all_bb & move_to > 0 {
-                new_move.add_delta(state.piece_at(move_to), move_to);
+            if let Some(captured) = state.piece_on(to) {
+                new_move.add_delta(captured, move_to);
                 new_move.add_delta(other_all, move_to);
             }
 
             // promotions
-            if move_to & 0xff000000000000ffu64 > 0 {
+            if move_to & PROMOTION_RANKS > 0 {
 
                 let promotions = if this_ofs == 0 {
                     [BBPiece::WRook, BBPiece::WKnight, BBPiece::WBishop, BBPiece::WQueen]
@@ -126,6 +157,7 @@ impl MoveGenerator {
                     
                     let mut new_move = new_move.clone();
 
+                    new_move.promote = Some(*p);
                     new_move.add_delta(*p, move_to);
 
                     if !self.is_king_hit(state, &new_move) {
@@ -134,7 +166,6 @@ impl MoveGenerator {
                     }
                 }
 
-                move_candidates ^= move_to;
                 continue;
             }
 
@@ -142,8 +173,6 @@ impl MoveGenerator {
                 debug_assert!(state_is_sane(state, &new_move));
                 moves.push(new_move);
             }
-
-            move_candidates ^= move_to;
         }
     }
 
@@ -155,8 +184,6 @@ impl MoveGenerator {
         move_from: BBoard,
         move_candidates: BBoard,
     ) {
-        let mut move_candidates = move_candidates;
-
         let (_this_ofs, other_ofs) = state.next_to_move.offsets();
 
         let (this_all, other_all) = if state.next_to_move == Side::White {
@@ -172,11 +199,10 @@ impl MoveGenerator {
             (BBPiece::BEnPassant, BBPiece::WEnPassant)
         };
         
-        let other_all_bb = state.bboard_ofs(BBPiece::WAll, other_ofs);
         let other_en_passant_bb = state.bboard_ofs(BBPiece::WEnPassant, other_ofs);
 
-        while move_candidates > 0 {
-            let move_to = move_candidates & (-Wrapping(move_candidates)).0;
+        for to in Bitboard(move_candidates) {
+            let move_to = to.bit();
 
             let move_delta = move_from | move_to;
 
@@ -217,17 +243,15 @@ impl MoveGenerator {
                 new_move.add_delta(other_en_passant, other_en_passant_bb);
             }
 
-            if  other_all_bb & move_to > 0 {
+            if let Some(captured) = state.piece_on(to) {
                 new_move.add_delta(other_all, move_to);
-                new_move.add_delta(state.piece_at(move_to), move_to);
+                new_move.add_delta(captured, move_to);
             }
 
             if !self.is_king_hit(state, &new_move) {
                 debug_assert!(state_is_sane(state, &new_move));
                 moves.push(new_move);
             }
-
-            move_candidates ^= move_to;
         }
     }
 
@@ -308,7 +332,7 @@ impl MoveGenerator {
         state.do_move(chess_move);
 
         debug_assert!(state.bboard_ofs(BBPiece::WKing, this_ofs) > 0);
-        let idx = state.bboard_ofs(BBPiece::WKing, this_ofs).trailing_zeros() as usize;
+        let idx = Square::from_bit(state.bboard_ofs(BBPiece::WKing, this_ofs)).idx();
 
         let result = self.is_hit(state, idx, other_ofs);
 
@@ -317,33 +341,186 @@ impl MoveGenerator {
         result
     }
 
-     #[inline]
-     fn is_any_hit(&self, state: &mut ChessState, check_board: BBoard, offset: usize) -> bool {
+    /// Computes checking squares and discovered check candidates of the side to move
+    /// against the opponent king.
+    pub fn check_info(&self, state: &ChessState) -> CheckInfo {
+        let (this_ofs, other_ofs) = state.next_to_move.offsets();
 
-        let mut check_board = check_board;
+        let king = state.bboard_ofs(BBPiece::WKing, other_ofs);
+        debug_assert!(king > 0);
+        let king_idx = Square::from_bit(king).idx();
 
-        while check_board > 0 {
-            let idx = last_bit(check_board).trailing_zeros() as usize;
+        let all = state.bboard(BBPiece::WAll) | state.bboard(BBPiece::BAll);
 
-            if self.is_hit(state, idx, offset) {
-                return true;
+        // use opposite pawn color to get the checking squares
+        let pawn_checks = if this_ofs == 0 {
+            self.move_provider.black_pawn_capture[king_idx]
+        } else {
+            self.move_provider.white_pawn_capture[king_idx]
+        };
+
+        let rook_checks = self.sliders.get_rook_attack_bits(king_idx, all);
+        let bishop_checks = self.sliders.get_bishop_attack_bits(king_idx, all);
+
+        let check_squares = [
+            0u64,
+            pawn_checks,
+            rook_checks,
+            self.move_provider.knight_move[king_idx],
+            bishop_checks,
+            rook_checks | bishop_checks,
+        ];
+
+        // own sliders aiming at the king through exactly one own piece
+        let queens = state.bboard_ofs(BBPiece::WQueen, this_ofs);
+        let snipers = (self.move_provider.rook_move[king_idx]
+            & (state.bboard_ofs(BBPiece::WRook, this_ofs) | queens))
+            | (self.move_provider.bishop_move[king_idx]
+                & (state.bboard_ofs(BBPiece::WBishop, this_ofs) | queens));
+
+        let own = state.bboard_ofs(BBPiece::WAll, this_ofs);
+        let mut discovered_candidates = 0u64;
+
+        for sniper in Bitboard(snipers) {
+            let blockers = self.between_bits(king_idx, sniper.idx()) & all;
+
+            if blockers.count_ones() == 1 {
+                discovered_candidates |= blockers & own;
             }
+        }
 
-            check_board = remove_last_bit(check_board);
+        CheckInfo {
+            king_idx,
+            check_squares,
+            discovered_candidates,
         }
+    }
 
-        false
+    /// Tells if the move of the side to move checks the opponent king, without making the move.
+    pub fn gives_check(&self, state: &ChessState, chess_move: &ChessMove) -> bool {
+        let info = self.check_info(state);
+
+        self.gives_check_with(state, &info, chess_move)
+    }
+
+    /// Same as `gives_check`, reusing check info computed once for the position.
+    pub fn gives_check_with(&self, state: &ChessState, info: &CheckInfo, chess_move: &ChessMove) -> bool {
+        let this_ofs = state.next_to_move.offset();
+
+        let piece = chess_move.get_piece();
+        let piece_type = piece.idx() - this_ofs;
+
+        let from_idx = Square::from_bit(chess_move.move_from).idx();
+        let to_idx = Square::from_bit(chess_move.move_to).idx();
+
+        let is_castle = piece_type == BBPiece::WKing.idx()
+            && (from_idx as i32 - to_idx as i32).abs() == 2;
+
+        let is_en_passant = piece_type == BBPiece::WPawn.idx()
+            && from_idx % 8 != to_idx % 8
+            && chess_move.move_to & state.bboard_ofs(BBPiece::WAll, state.next_to_move.opposite().offset()) == 0;
+
+        if is_castle || is_en_passant || chess_move.promote.is_some() {
+            // rare cases which change more than the moving piece, check them exactly
+            return self.is_hit_after(state, chess_move, info.king_idx);
+        }
+
+        // direct check
+        if info.check_squares[piece_type] & chess_move.move_to > 0 {
+            return true;
+        }
+
+        // discovered check, unless the piece moves along the line to the king
+        info.discovered_candidates & chess_move.move_from > 0
+            && self.line_bits(info.king_idx, from_idx) & chess_move.move_to == 0
+    }
+
+    /// Tells if the opponent king square is attacked once the move is applied,
+    /// by applying the move deltas to local copies of the side to move boards.
+    fn is_hit_after(&self, state: &ChessState, chess_move: &ChessMove, king_idx: usize) -> bool {
+        let this_ofs = state.next_to_move.offset();
+
+        let mut boards = [0u64; 6];
+        for (idx, board) in boards.iter_mut().enumerate() {
+            *board = state.bboard_ofs(BBPiece::from_usize(idx), this_ofs);
+        }
+
+        let mut all = state.bboard(BBPiece::WAll) | state.bboard(BBPiece::BAll);
+        let mut all_delta = 0u64;
+
+        for (piece, delta) in chess_move.deltas.iter() {
+            match *piece {
+                BBPiece::WAll | BBPiece::BAll => all_delta ^= *delta,
+                p if p.get_side() == state.next_to_move && p.idx() - this_ofs < boards.len() => {
+                    boards[p.idx() - this_ofs] ^= *delta;
+                }
+                _ => {}
+            }
+        }
+
+        // a capture shows up in both all boards, the square stays occupied
+        all ^= all_delta & !chess_move.move_to;
+        all |= chess_move.move_to;
+
+        let pawn_checks = if this_ofs == 0 {
+            self.move_provider.black_pawn_capture[king_idx]
+        } else {
+            self.move_provider.white_pawn_capture[king_idx]
+        };
+
+        let rook_moves = self.sliders.get_rook_attack_bits(king_idx, all);
+        let bishop_moves = self.sliders.get_bishop_attack_bits(king_idx, all);
+
+        boards[BBPiece::WPawn.idx()] & pawn_checks > 0
+            || boards[BBPiece::WKnight.idx()] & self.move_provider.knight_move[king_idx] > 0
+            || (boards[BBPiece::WRook.idx()] | boards[BBPiece::WQueen.idx()]) & rook_moves > 0
+            || (boards[BBPiece::WBishop.idx()] | boards[BBPiece::WQueen.idx()]) & bishop_moves > 0
+    }
+
+    /// Squares strictly between two squares on a common rank, file or diagonal,
+    /// or zero if the squares are not aligned.
+    pub fn between_bits(&self, a_idx: usize, b_idx: usize) -> BBoard {
+        let (a, b) = (Square::from_idx(a_idx).bit(), Square::from_idx(b_idx).bit());
+
+        if self.move_provider.rook_move[a_idx] & b > 0 {
+            self.sliders.get_rook_attack_bits(a_idx, b) & self.sliders.get_rook_attack_bits(b_idx, a)
+        } else if self.move_provider.bishop_move[a_idx] & b > 0 {
+            self.sliders.get_bishop_attack_bits(a_idx, b) & self.sliders.get_bishop_attack_bits(b_idx, a)
+        } else {
+            0
+        }
+    }
+
+    /// The full board line through two aligned squares, or zero if the squares are not aligned.
+    pub fn line_bits(&self, a_idx: usize, b_idx: usize) -> BBoard {
+        let (a, b) = (Square::from_idx(a_idx).bit(), Square::from_idx(b_idx).bit());
+
+        if self.move_provider.rook_move[a_idx] & b > 0 {
+            (self.move_provider.rook_move[a_idx] & self.move_provider.rook_move[b_idx]) | a | b
+        } else if self.move_provider.bishop_move[a_idx] & b > 0 {
+            (self.move_provider.bishop_move[a_idx] & self.move_provider.bishop_move[b_idx]) | a | b
+        } else {
+            0
+        }
+    }
+
+     #[inline]
+     fn is_any_hit(&self, state: &mut ChessState, check_board: BBoard, offset: usize) -> bool {
+
+        Bitboard(check_board)
+            .into_iter()
+            .any(|square| self.is_hit(state, square.idx(), offset))
     }
     
 
     #[inline]
-    fn is_hit(&self, state: &mut ChessState, idx: usize, offset: usize) -> bool {
+    pub(crate) fn is_hit(&self, state: &mut ChessState, idx: usize, offset: usize) -> bool {
 
         // use opposite pawn color to get source
         let pawns_capture = if offset == 0 {
-            self.move_provider.black_pawn_capture
+            &self.move_provider.black_pawn_capture
         } else {
-            self.move_provider.white_pawn_capture
+            &self.move_provider.white_pawn_capture
         };
 
         if state.bboard_ofs(BBPiece::WPawn, offset) & pawns_capture[idx] > 0 {
@@ -356,8 +533,8 @@ impl MoveGenerator {
 
         let all = state.bboard(BBPiece::WAll) | state.bboard(BBPiece::BAll);
 
-        let rook_moves = self.magic.get_rook_attack_bits(idx, all);
-        let bishop_moves = self.magic.get_bishop_attack_bits(idx, all);
+        let rook_moves = self.sliders.get_rook_attack_bits(idx, all);
+        let bishop_moves = self.sliders.get_bishop_attack_bits(idx, all);
 
         if state.bboard_ofs(BBPiece::WRook, offset) & rook_moves > 0 {
             return true;
@@ -379,9 +556,238 @@ impl MoveGenerator {
         false
     }
 
+    /// Tells if the square is attacked by any piece of the given side.
+    #[inline]
+    pub fn is_attacked(&self, state: &mut ChessState, idx: usize, side: Side) -> bool {
+        self.is_hit(state, idx, side.offset())
+    }
+
+    /// Tells if the king of the side to move is attacked.
+    #[inline]
+    pub fn is_in_check(&self, state: &mut ChessState) -> bool {
+        let (this_ofs, other_ofs) = state.next_to_move.offsets();
+
+        let idx = Square::from_bit(state.bboard_ofs(BBPiece::WKing, this_ofs)).idx();
+
+        self.is_hit(state, idx, other_ofs)
+    }
+
+    /// Returns the pieces of both sides attacking the square, with sliders
+    /// computed against the given occupancy. Pieces missing from the occupancy
+    /// are not removed from the result, mask it with the occupancy if needed.
+    pub fn attackers_to_occ(&self, state: &ChessState, idx: usize, occupancy: BBoard) -> BBoard {
+        let rook_moves = self.sliders.get_rook_attack_bits(idx, occupancy);
+        let bishop_moves = self.sliders.get_bishop_attack_bits(idx, occupancy);
+
+        let queens = state.bboard(BBPiece::WQueen) | state.bboard(BBPiece::BQueen);
+        let rooks = state.bboard(BBPiece::WRook) | state.bboard(BBPiece::BRook) | queens;
+        let bishops = state.bboard(BBPiece::WBishop) | state.bboard(BBPiece::BBishop) | queens;
+        let knights = state.bboard(BBPiece::WKnight) | state.bboard(BBPiece::BKnight);
+        let kings = state.bboard(BBPiece::WKing) | state.bboard(BBPiece::BKing);
+
+        // use opposite pawn color to get source
+        (self.move_provider.black_pawn_capture[idx] & state.bboard(BBPiece::WPawn))
+            | (self.move_provider.white_pawn_capture[idx] & state.bboard(BBPiece::BPawn))
+            | (self.move_provider.knight_move[idx] & knights)
+            | (self.move_provider.king_move[idx] & kings)
+            | (rook_moves & rooks)
+            | (bishop_moves & bishops)
+    }
+
+    /// Returns all pieces of the given side attacking the square.
+    pub fn attackers_to(&self, state: &ChessState, idx: usize, side: Side) -> BBoard {
+        let all = state.bboard(BBPiece::WAll) | state.bboard(BBPiece::BAll);
+
+        self.attackers_to_occ(state, idx, all) & state.bboard_ofs(BBPiece::WAll, side.offset())
+    }
+
+    /// Returns the sliders of the given side which attack the square once the
+    /// `through` pieces are removed from the board, without the direct attackers.
+    pub fn xray_attackers(&self, state: &ChessState, idx: usize, side: Side, through: BBoard) -> BBoard {
+        let all = state.bboard(BBPiece::WAll) | state.bboard(BBPiece::BAll);
+        let ofs = side.offset();
+
+        let sliders = state.bboard_ofs(BBPiece::WRook, ofs)
+            | state.bboard_ofs(BBPiece::WBishop, ofs)
+            | state.bboard_ofs(BBPiece::WQueen, ofs);
+
+        let direct = self.attackers_to_occ(state, idx, all);
+        let xray = self.attackers_to_occ(state, idx, all & !through);
+
+        xray & !direct & sliders & !through
+    }
+
+    /// Returns all squares attacked by the given side, including squares
+    /// occupied by own pieces (defended pieces).
+    pub fn attacked_squares(&self, state: &ChessState, side: Side) -> BBoard {
+        let ofs = side.offset();
+        let all = state.bboard(BBPiece::WAll) | state.bboard(BBPiece::BAll);
+
+        let pawn_captures = if side == Side::White {
+            &self.move_provider.white_pawn_capture
+        } else {
+            &self.move_provider.black_pawn_capture
+        };
+
+        let mut result = 0u64;
+        for square in Bitboard(state.bboard_ofs(BBPiece::WAll, ofs)) {
+            let idx = square.idx();
+
+            let piece = state.piece_on(square).map_or(BBPiece::WKing, |p| BBPiece::from_usize(p.idx() - ofs));
+
+            result |= match piece {
+                BBPiece::WPawn => pawn_captures[idx],
+                BBPiece::WKnight => self.move_provider.knight_move[idx],
+                BBPiece::WBishop => self.sliders.get_bishop_attack_bits(idx, all),
+                BBPiece::WRook => self.sliders.get_rook_attack_bits(idx, all),
+                BBPiece::WQueen => {
+                    self.sliders.get_bishop_attack_bits(idx, all) | self.sliders.get_rook_attack_bits(idx, all)
+                }
+                _ => self.move_provider.king_move[idx],
+            };
+        }
+
+        result
+    }
+
+    /// Finds the least valuable piece of the given side among the attackers.
+    /// Returns the piece and its single bit board.
+    pub fn least_valuable_attacker(
+        &self,
+        state: &ChessState,
+        attackers: BBoard,
+        side: Side,
+    ) -> Option<(BBPiece, BBoard)> {
+        let ofs = side.offset();
+
+        let order = [
+            BBPiece::WPawn,
+            BBPiece::WKnight,
+            BBPiece::WBishop,
+            BBPiece::WRook,
+            BBPiece::WQueen,
+            BBPiece::WKing,
+        ];
+
+        for piece in order.iter() {
+            if let Some(square) = Bitboard(attackers & state.bboard_ofs(*piece, ofs)).first() {
+                return Some((BBPiece::from_usize(piece.idx() + ofs), square.bit()));
+            }
+        }
+
+        None
+    }
+
+    /// Static exchange evaluation: material balance for the side to move after
+    /// the sequence of captures on the target square, both sides always
+    /// recapturing with the least valuable attacker and free to stop.
+    pub fn see(&self, state: &ChessState, chess_move: &ChessMove) -> i32 {
+        let to_idx = Square::from_bit(chess_move.move_to).idx();
+
+        let mut occupancy = (state.bboard(BBPiece::WAll) | state.bboard(BBPiece::BAll)) ^ chess_move.move_from;
+
+        let mut gain = [0i32; 32];
+
+        if let Some(captured) = chess_move.captured_piece() {
+            gain[0] = captured.value().abs();
+
+            // en-passant removes a pawn next to the target square
+            let other_pawn = BBPiece::from_usize(BBPiece::WPawn.idx() + chess_move.side.opposite().offset());
+            for (piece, delta) in chess_move.deltas.iter() {
+                if *piece == other_pawn && *delta != chess_move.move_to {
+                    occupancy ^= *delta;
+                }
+            }
+        }
+
+        let mut on_square = chess_move.get_piece().value().abs();
+
+        if let Some(promoted) = chess_move.promote {
+            gain[0] += promoted.value().abs() - BBPiece::WPawn.value();
+            on_square = promoted.value().abs();
+        }
+
+        occupancy |= chess_move.move_to;
+
+        let mut side = chess_move.side.opposite();
+        let mut depth = 0usize;
+
+        loop {
+            let attackers = self.attackers_to_occ(state, to_idx, occupancy) & occupancy;
+
+            let lva = self.least_valuable_attacker(state, attackers & state.bboard_ofs(BBPiece::WAll, side.offset()), side);
+
+            let (piece, square) = match lva {
+                Some(lva) => lva,
+                None => break,
+            };
+
+            if depth + 1 >= gain.len() {
+                break;
+            }
+
+            depth += 1;
+
+            // speculative score if the piece on the square is captured
+            gain[depth] = on_square - gain[depth - 1];
+
+            // losing either way, the previous capture stands
+            if cmp::max(-gain[depth - 1], gain[depth]) < 0 {
+                depth -= 1;
+                break;
+            }
+
+            occupancy ^= square;
+            on_square = piece.value().abs();
+            side = side.opposite();
+        }
+
+        while depth > 0 {
+            gain[depth - 1] = -cmp::max(-gain[depth - 1], gain[depth]);
+            depth -= 1;
+        }
+
+        gain[0]
+    }
+
     /// Function generates all possible moves from a given position, and fills them
     /// to the `moves` array. It returns the number of unique correct moves generated.
     pub fn generate_moves(&self, state: &mut ChessState, moves: &mut Vec<ChessMove>) {
+        self.generate_filtered(state, moves, GenType::All, !0u64);
+    }
+
+    /// Generates only captures, en-passant captures and promotions.
+    pub fn generate_captures(&self, state: &mut ChessState, moves: &mut Vec<ChessMove>) {
+        self.generate_filtered(state, moves, GenType::Captures, !0u64);
+    }
+
+    /// Generates the moves that are left out by `generate_captures`, including castles.
+    pub fn generate_quiets(&self, state: &mut ChessState, moves: &mut Vec<ChessMove>) {
+        self.generate_filtered(state, moves, GenType::Quiets, !0u64);
+    }
+
+    /// Checks a move computed for some other position (hash move, killer move) against
+    /// the current state. Returns a freshly generated legal move with the same
+    /// coordinates and promotion, or `None` if there is no such move.
+    pub fn legal_move(&self, state: &mut ChessState, chess_move: &ChessMove) -> Option<ChessMove> {
+        if chess_move.move_from & state.bboard_ofs(BBPiece::WAll, state.next_to_move.offset()) == 0 {
+            return None;
+        }
+
+        let mut moves: Vec<ChessMove> = Vec::with_capacity(8);
+
+        self.generate_filtered(state, &mut moves, GenType::All, chess_move.move_from);
+
+        moves.into_iter().find(|m| m.is_same(chess_move))
+    }
+
+    fn generate_filtered(
+        &self,
+        state: &mut ChessState,
+        moves: &mut Vec<ChessMove>,
+        gen_type: GenType,
+        from_mask: BBoard,
+    ) {
 
         let (this_ofs, other_ofs) = state.next_to_move.offsets();
 
@@ -390,6 +796,13 @@ impl MoveGenerator {
 
         let all_pieces = all_own_pieces_bb | all_enemy_pieces_bb;
 
+        // target squares for the pieces other than pawns
+        let targets = match gen_type {
+            GenType::All => !all_own_pieces_bb,
+            GenType::Captures => all_enemy_pieces_bb,
+            GenType::Quiets => !all_pieces,
+        };
+
         //
         let (this_king, this_rook, this_knight, this_bishop, this_queen) = 
         if state.next_to_move == Side::White {
@@ -399,12 +812,10 @@ impl MoveGenerator {
         };
 
         //////////////////////// pawns
-        let mut pawns = state.bboard_ofs(BBPiece::WPawn, this_ofs);
-
-        while pawns > 0 {
-            let move_from = last_bit(pawns);
+        let pawns = state.bboard_ofs(BBPiece::WPawn, this_ofs) & from_mask;
 
-            let from_idx = move_from.trailing_zeros() as usize;
+        for from in Bitboard(pawns) {
+            let (move_from, from_idx) = (from.bit(), from.idx());
 
             let (pawn_moves, pawn_captures) = match state.next_to_move {
                 Side::White => (
@@ -417,87 +828,86 @@ impl MoveGenerator {
                 ),
             };
 
-            let move_candidates = (pawn_moves[from_idx] & !all_own_pieces_bb & !all_enemy_pieces_bb)
-                | (pawn_captures[from_idx]
-                    & !all_own_pieces_bb
-                    & (all_enemy_pieces_bb | state.bboard_ofs(BBPiece::WEnPassant, other_ofs)));
+            let pushes = pawn_moves[from_idx] & !all_own_pieces_bb & !all_enemy_pieces_bb;
+            let captures = pawn_captures[from_idx]
+                & !all_own_pieces_bb
+                & (all_enemy_pieces_bb | state.bboard_ofs(BBPiece::WEnPassant, other_ofs));
 
-            self.fill_pawn_moves(state, moves, move_from, move_candidates);
+            // promotions are generated together with captures
+            let move_candidates = match gen_type {
+                GenType::All => pushes | captures,
+                GenType::Captures => captures | (pushes & PROMOTION_RANKS),
+                GenType::Quiets => pushes & !PROMOTION_RANKS,
+            };
 
-            pawns = remove_last_bit(pawns);
+            self.fill_pawn_moves(state, moves, move_from, move_candidates);
         }
 
         ////////////////////////// rooks
-        let mut rooks = state.bboard(this_rook);
-        while rooks > 0 {
-            let move_from = last_bit(rooks);
+        let rooks = state.bboard(this_rook) & from_mask;
 
-            let from_idx = move_from.trailing_zeros() as usize;
+        for from in Bitboard(rooks) {
+            let (move_from, from_idx) = (from.bit(), from.idx());
 
             let move_candidates =
-                self.magic.get_rook_attack_bits(from_idx, all_pieces) & !all_own_pieces_bb;
+                self.sliders.get_rook_attack_bits(from_idx, all_pieces) & targets;
 
             self.fill_rbqn_moves(state, moves, this_rook, move_from, move_candidates);
-
-            rooks = remove_last_bit(rooks);
         }
 
         //////////////////////// knights
-        let mut knights = state.bboard(this_knight);
-        while knights > 0 {
-            let move_from = last_bit(knights);
+        let knights = state.bboard(this_knight) & from_mask;
 
-            let from_idx = move_from.trailing_zeros() as usize;
+        for from in Bitboard(knights) {
+            let (move_from, from_idx) = (from.bit(), from.idx());
 
-            let move_candidates = self.move_provider.knight_move[from_idx] & !all_own_pieces_bb;
+            let move_candidates = self.move_provider.knight_move[from_idx] & targets;
 
             self.fill_rbqn_moves(state, moves, this_knight, move_from, move_candidates);
-
-            knights = remove_last_bit(knights);
         }
 
         ///////////////////////// bishops
-        let mut bishops = state.bboard(this_bishop);
-        while bishops > 0 {
-            let move_from = last_bit(bishops);
+        let bishops = state.bboard(this_bishop) & from_mask;
 
-            let from_idx = move_from.trailing_zeros() as usize;
+        for from in Bitboard(bishops) {
+            let (move_from, from_idx) = (from.bit(), from.idx());
 
             let move_candidates =
-                self.magic.get_bishop_attack_bits(from_idx, all_pieces) & !all_own_pieces_bb;
+                self.sliders.get_bishop_attack_bits(from_idx, all_pieces) & targets;
 
             self.fill_rbqn_moves(state, moves, this_bishop, move_from, move_candidates);
-
-            bishops = remove_last_bit(bishops);
         }
 
         ///////////////////////// queens
-        let mut queens = state.bboard(this_queen);
-        while queens > 0 {
-            let move_from = last_bit(queens);
+        let queens = state.bboard(this_queen) & from_mask;
 
-            let from_idx = move_from.trailing_zeros() as usize;
+        for from in Bitboard(queens) {
+            let (move_from, from_idx) = (from.bit(), from.idx());
 
-            let move_candidates = (self.magic.get_rook_attack_bits(from_idx, all_pieces)
-                | self.magic.get_bishop_attack_bits(from_idx, all_pieces))
-                & !all_own_pieces_bb;
+            let move_candidates = (self.sliders.get_rook_attack_bits(from_idx, all_pieces)
+                | self.sliders.get_bishop_attack_bits(from_idx, all_pieces))
+                & targets;
                 
             self.fill_rbqn_moves(state, moves, this_queen, move_from, move_candidates);
-
-            queens = remove_last_bit(queens);
         }
 
         //////////////////////// king
-        let move_from = state.bboard(this_king);
+        let move_from = state.bboard(this_king) & from_mask;
+
+        if move_from == 0 {
+            return;
+        }
 
-        let from_idx = move_from.trailing_zeros() as usize;
+        let from_idx = Square::from_bit(move_from).idx();
 
-        let move_candidates = self.move_provider.king_move[from_idx] & !all_own_pieces_bb;
+        let move_candidates = self.move_provider.king_move[from_idx] & targets;
 
         self.fill_rbqn_moves(state, moves, this_king, move_from, move_candidates);
 
         // add castle moves
-        self.fill_castle_moves(state, moves);
+        if gen_type != GenType::Captures {
+            self.fill_castle_moves(state, moves);
+        }
 
     }
 }
@@ -511,12 +921,12 @@ pub fn get_bb_move_text(
     let mut move_str = String::new();
 
     move_str.push_str(piece.to_string().as_str());
-    move_str.push_str(" ");
+    move_str.push(' ');
     move_str.push_str(bb_to_coord(move_from).as_str());
-    move_str.push_str("-");
+    move_str.push('-');
     move_str.push_str(bb_to_coord(move_to).as_str());
 
-    return move_str;
+    move_str
 }
 
 
@@ -526,53 +936,75 @@ pub fn get_move_text(chess_move: &ChessMove) -> String {
 
 }
 
-#[cfg(test)]
-mod tests {
-    use std::time::Instant;
-
-    use crate::debug::Demo;
+#[inline]
+fn state_is_sane(state: &mut ChessState, chess_move: &ChessMove) -> bool {
 
-    use super::*;
+    state.do_move(chess_move);
 
-    fn perft_recursion(move_generator: &MoveGenerator, depth: u32, state: &mut ChessState) -> usize {
-        if depth == 0 {
-            return 1;
-        }
+    let all = state.bboard(BBPiece::WAll) | state.bboard(BBPiece::BAll) ;
 
-        let mut result = 0;
+    let mut all2 = 0u64;
 
-        let mut new_moves: Vec<ChessMove> = Vec::with_capacity(20);
+    for piece in BBPiece::get_pieces() {
+        all2 ^= state.bboard(*piece);
+    }
 
-        move_generator.generate_moves(state, &mut new_moves);
+    let mut result = true;
 
-        /*
-        use chess::MoveGen;
-        use chess::Board;
-        use std::str::FromStr;
+    if (state.next_to_move == Side::White && state.bboard(BBPiece::WEnPassant) > 0 )
+       || (state.next_to_move == Side::Black && state.bboard(BBPiece::BEnPassant) > 0) {
+        println!("En-passant incompatible with the current player:");
+        result = false;
+    }
 
-        let fen = state.to_fen();
-        let board = Board::from_str(&fen);
-        if board.is_err() {
-            println!("fen parser error for board:{}", fen);
-        }
-        let board = board.unwrap();
+    if (state.bboard(BBPiece::BEnPassant) & 0xffff00ffffffffffu64 > 0)
+       || (state.bboard(BBPiece::WEnPassant) & 0xffffffffff00ffffu64 > 0) {
+        println!("Impossible en-passant position:");
         
-        let iterable = MoveGen::new_legal(&board);
-        if new_moves.len() != iterable.len() {
-            println!("Count failure for position {}, expected {}, found {}", fen, iterable.len(), new_moves.len());
-            
-            panic!();
-        }
-*/
-        for m in new_moves.iter() {
-            state.do_move(m);
-            result += perft_recursion(move_generator, depth - 1, state);
-            state.undo_move(m);
-        }
+        result = false;
+    }
 
-        result
+    if state.bboard(BBPiece::WKing).count_ones() != 1
+       || state.bboard(BBPiece::BKing).count_ones() != 1 {
+        println!("Impossible king state:");
+     
+        result = false;
+    }
+
+    if all != all2 {
+        println!("Inconsistent all piece bitboard state:");
+     
+        result = false;
+    }
+
+    if !result {
+        println!("State sanity failed!");
+        state.demo();
+        state.undo_move(chess_move);
+
+        println!("State before move:");
+        println!("{}", state.to_fen());
+        println!("Last move info");
+        chess_move.demo();
+    
+        return result;
     }
 
+    state.undo_move(chess_move);
+
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use crate::debug::Demo;
+    use crate::perft::perft;
+
+    use super::*;
+
     fn perft_test(fen_state: &str, depth: u32, expected_move_count: usize) {
 
         let mut state = ChessState::from_fen(fen_state);
@@ -584,7 +1016,7 @@ mod tests {
 
         let move_generator = MoveGenerator::new();
 
-        let move_count = perft_recursion(&move_generator, depth, &mut state);
+        let move_count = perft(&move_generator, depth, &mut state) as usize;
 
         println!(
             "found:    {},\nexpected: {}",
@@ -693,6 +1125,139 @@ mod tests {
         );*/
     }
 
+    #[test]
+    fn test_shared_tables() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<MoveGenerator>();
+
+        let generator = MoveGenerator::new();
+        let other = MoveGenerator::new();
+
+        // all generators use the same tables
+        assert!(std::ptr::eq(generator.sliders, other.sliders));
+        assert!(std::ptr::eq(generator.move_provider, other.move_provider));
+
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -";
+
+        let counts: Vec<u64> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..2)
+                .map(|_| scope.spawn(|| perft(&generator, 2, &mut ChessState::from_fen(fen))))
+                .collect();
+
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        assert_eq!(counts, vec![2039, 2039]);
+    }
+
+    fn check_gives_check(generator: &MoveGenerator, depth: u32, state: &mut ChessState) -> usize {
+        let mut moves: Vec<ChessMove> = Vec::new();
+        generator.generate_moves(state, &mut moves);
+
+        let info = generator.check_info(state);
+        let mut checks = 0;
+
+        for m in moves.iter() {
+            let gives_check = generator.gives_check_with(state, &info, m);
+
+            state.do_move(m);
+
+            let other_ofs = state.next_to_move.opposite().offset();
+            let king_idx = Square::from_bit(state.bboard_ofs(BBPiece::WKing, state.next_to_move.offset())).idx();
+            let in_check = generator.is_hit(state, king_idx, other_ofs);
+
+            assert_eq!(gives_check, in_check, "{} after {}", state.to_fen(), m);
+
+            if in_check {
+                checks += 1;
+            }
+
+            if depth > 1 {
+                checks += check_gives_check(generator, depth - 1, state);
+            }
+
+            state.undo_move(m);
+        }
+
+        checks
+    }
+
+    #[test]
+    fn test_gives_check() {
+        let generator = MoveGenerator::new();
+
+        // https://www.chessprogramming.org/Perft_Results, check counts of positions 2 and 3
+        let mut state = ChessState::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -");
+        assert_eq!(check_gives_check(&generator, 2, &mut state), 3);
+
+        let mut state = ChessState::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - ");
+        assert_eq!(check_gives_check(&generator, 3, &mut state), 2 + 10 + 267);
+
+        // discovered checks, castle checks, promotion and en-passant checks
+        let fens = [
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+            "8/8/8/1k6/3Pp3/8/8/4KQ2 b - d3 0 1",
+            "7k/8/8/8/3N4/8/8/Q3K3 w - - 0 1",
+            "4k3/8/8/8/4B3/8/4R3/K7 w - - 0 1",
+        ];
+
+        for fen in fens.iter() {
+            let mut state = ChessState::from_fen(fen);
+            check_gives_check(&generator, 2, &mut state);
+        }
+    }
+
+    #[test]
+    fn test_attack_map() {
+        let generator = MoveGenerator::new();
+        let state = ChessState::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+
+        // second and third ranks plus all pieces except the rooks on the first rank
+        assert_eq!(generator.attacked_squares(&state, Side::White), 0x0000_0000_00ff_ff7eu64);
+        assert_eq!(generator.attacked_squares(&state, Side::Black), 0x7eff_ff00_0000_0000u64);
+
+        // f3 is attacked by the g1 knight and the e2 and g2 pawns
+        let f3 = 21;
+        assert_eq!(generator.attackers_to(&state, f3, Side::White), 1 << 6 | 1 << 12 | 1 << 14);
+        assert_eq!(generator.attackers_to(&state, f3, Side::Black), 0);
+
+        let lva = generator.least_valuable_attacker(&state, 1 << 6 | 1 << 12, Side::White);
+        assert_eq!(lva, Some((BBPiece::WPawn, 1 << 12)));
+
+        // rook behind a rook on the e-file
+        let state = ChessState::from_fen("4k3/8/8/4p3/8/8/4R3/4R1K1 w - - 0 1");
+        let e5 = 36;
+        assert_eq!(generator.attackers_to(&state, e5, Side::White), 1 << 12);
+        assert_eq!(generator.xray_attackers(&state, e5, Side::White, 1 << 12), 1 << 4);
+        assert_eq!(generator.least_valuable_attacker(&state, 0, Side::White), None);
+    }
+
+    #[test]
+    fn test_see() {
+        let generator = MoveGenerator::new();
+
+        // https://www.chessprogramming.org/SEE_-_The_Swap_Algorithm
+        let state = ChessState::from_fen("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - -");
+        let m = ChessMove::parse("e1e5", &state).unwrap();
+        assert_eq!(generator.see(&state, &m), 100);
+
+        let state = ChessState::from_fen("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - -");
+        let m = ChessMove::parse("d3e5", &state).unwrap();
+        assert_eq!(generator.see(&state, &m), 100 - 320);
+
+        // a quiet move to an attacked square loses the piece
+        let state = ChessState::from_fen("4k3/8/3p4/8/8/2B5/8/4K3 w - - 0 1");
+        let m = ChessMove::parse("c3e5", &state).unwrap();
+        assert_eq!(generator.see(&state, &m), -330);
+
+        // rooks doubled behind each other win the pawn
+        let state = ChessState::from_fen("4k3/4r3/8/4p3/8/8/4R3/4R1K1 w - - 0 1");
+        let m = ChessMove::parse("e2e5", &state).unwrap();
+        assert_eq!(generator.see(&state, &m), 100);
+    }
+
     #[test]
     fn test_some_moves() {
         let mut state = ChessState::from_fen("r3k1B1/8/3b4/p1pPNR1n/2P5/2N4P/PP5P/R2Q2K1 b q - 0 1");
@@ -709,7 +1274,6 @@ mod tests {
         for (idx, cmove) in moves.iter().enumerate() {
             print!("{} ", idx + 1);
 
-            state.do_move(cmove);
             print_move_info(&mut state, cmove);
 
             debug_assert!(state_is_sane(&mut state, cmove));
@@ -718,7 +1282,7 @@ mod tests {
 
     fn print_move_info(state: &mut ChessState, chess_move: &ChessMove) {
 
-        let move_info = get_move_text(&chess_move);
+        let move_info = get_move_text(chess_move);
         println!(
             "{}    ======================================================",
             move_info
@@ -731,63 +1295,3 @@ mod tests {
         state.undo_move(chess_move);
     }
 }
-
-#[inline]
-fn state_is_sane(state: &mut ChessState, chess_move: &ChessMove) -> bool {
-
-    state.do_move(chess_move);
-
-    let all = state.bboard(BBPiece::WAll) | state.bboard(BBPiece::BAll) ;
-
-    let mut all2 = 0u64;
-
-    for piece in BBPiece::get_pieces() {
-        all2 ^= state.bboard(*piece);
-    }
-
-    let mut result = true;
-
-    if (state.next_to_move == Side::White && state.bboard(BBPiece::WEnPassant) > 0 )
-       || (state.next_to_move == Side::Black && state.bboard(BBPiece::BEnPassant) > 0) {
-        println!("En-passant incompatible with the current player:");
-        result = false;
-    }
-
-    if (state.bboard(BBPiece::BEnPassant) & 0xffff00ffffffffffu64 > 0)
-       || (state.bboard(BBPiece::WEnPassant) & 0xffffffffff00ffffu64 > 0) {
-        println!("Impossible en-passant position:");
-        
-        result = false;
-    }
-
-    if state.bboard(BBPiece::WKing).count_ones() != 1
-       || state.bboard(BBPiece::BKing).count_ones() != 1 {
-        println!("Impossible king state:");
-     
-        result = false;
-    }
-
-    if all != all2 {
-        println!("Inconsistent all piece bitboard state:");
-     
-        result = false;
-    }
-
-    if !result {
-        println!("State sanity failed!");
-        state.demo();
-        state.undo_move(chess_move);
-
-        println!("State before move:");
-        println!("{}", state.to_fen());
-        println!("Last move info");
-        chess_move.demo();
-    
-        return result;
-    }
-
-    state.undo_move(chess_move);
-
-
-    result
-}
@@ -0,0 +1,299 @@
+use crate::game_setup::ChessMove;
+use crate::move_generator::MoveGenerator;
+use crate::state::ChessState;
+
+/// Stages of the move picker, in the order they are visited
+#[derive(Copy, Clone, Eq, PartialEq, PartialOrd, Debug)]
+enum Stage {
+    HashMove,
+    GenerateCaptures,
+    GoodCaptures,
+    Killers,
+    GenerateQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+/// Lazily yields the legal moves of a position, best candidates first:
+/// hash move, good captures, killer moves, quiet moves and finally bad captures.
+/// Each category is generated only once the previous one is exhausted, so a cutoff
+/// on the hash move saves the whole move generation.
+pub struct MovePicker {
+    stage: Stage,
+    hash_move: Option<ChessMove>,
+    killers: [Option<ChessMove>; 2],
+    killer_idx: usize,
+    captures: Vec<(i32, ChessMove)>,
+    bad_captures: Vec<ChessMove>,
+    quiets: Vec<ChessMove>,
+    captures_only: bool,
+}
+
+impl MovePicker {
+    pub fn new(hash_move: Option<ChessMove>, killers: [Option<ChessMove>; 2]) -> MovePicker {
+        MovePicker {
+            stage: Stage::HashMove,
+            hash_move,
+            killers,
+            killer_idx: 0,
+            captures: Vec::with_capacity(16),
+            bad_captures: Vec::new(),
+            quiets: Vec::new(),
+            captures_only: false,
+        }
+    }
+
+    /// Picker for the quiescence search, which yields only the good captures
+    /// and promotions, best first.
+    pub fn captures() -> MovePicker {
+        MovePicker {
+            stage: Stage::GenerateCaptures,
+            captures_only: true,
+            ..MovePicker::new(None, [None, None])
+        }
+    }
+
+    /// Returns the next move to try, or `None` once all moves have been returned.
+    /// The state must be the same for all calls on one picker.
+    pub fn next_move(&mut self, generator: &MoveGenerator, state: &mut ChessState) -> Option<ChessMove> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GenerateCaptures;
+
+                    // replace the stored move with its legal version, or drop it
+                    self.hash_move = self
+                        .hash_move
+                        .take()
+                        .and_then(|m| generator.legal_move(state, &m));
+
+                    if let Some(hash_move) = &self.hash_move {
+                        return Some(hash_move.clone());
+                    }
+                }
+
+                Stage::GenerateCaptures => {
+                    self.stage = Stage::GoodCaptures;
+
+                    let mut moves: Vec<ChessMove> = Vec::with_capacity(16);
+                    generator.generate_captures(state, &mut moves);
+
+                    for m in moves {
+                        if self.is_hash_move(&m) {
+                            continue;
+                        }
+
+                        let victim = m.captured_piece().map_or(0, |p| p.value().abs());
+                        let attacker = m.get_piece().value().abs();
+                        let promotion = m.promote.map_or(0, |p| p.value().abs());
+
+                        // trading down is still good when the exchange does not lose material
+                        let is_good = victim >= attacker || generator.see(state, &m) >= 0;
+
+                        if is_good {
+                            // most valuable victim, least valuable attacker
+                            self.captures.push(((victim + promotion) * 10 - attacker, m));
+                        } else {
+                            self.bad_captures.push(m);
+                        }
+                    }
+                }
+
+                Stage::GoodCaptures => {
+                    if self.captures.is_empty() {
+                        self.stage = if self.captures_only { Stage::Done } else { Stage::Killers };
+                        continue;
+                    }
+
+                    let mut best = 0usize;
+
+                    for (idx, (score, _)) in self.captures.iter().enumerate() {
+                        if *score > self.captures[best].0 {
+                            best = idx;
+                        }
+                    }
+
+                    return Some(self.captures.swap_remove(best).1);
+                }
+
+                Stage::Killers => {
+                    if self.killer_idx >= self.killers.len() {
+                        self.stage = Stage::GenerateQuiets;
+                        continue;
+                    }
+
+                    let idx = self.killer_idx;
+                    self.killer_idx += 1;
+
+                    let killer = self.killers[idx]
+                        .take()
+                        .and_then(|m| generator.legal_move(state, &m))
+                        .filter(|m| !m.is_capture() && m.promote.is_none())
+                        .filter(|m| !self.is_hash_move(m) && !self.is_killer_move(m, idx));
+
+                    if let Some(killer) = killer {
+                        self.killers[idx] = Some(killer.clone());
+                        return Some(killer);
+                    }
+                }
+
+                Stage::GenerateQuiets => {
+                    self.stage = Stage::Quiets;
+
+                    generator.generate_quiets(state, &mut self.quiets);
+
+                    // moves are taken from the back, keep the generation order
+                    self.quiets.reverse();
+                }
+
+                Stage::Quiets => {
+                    match self.quiets.pop() {
+                        Some(m) => {
+                            if self.is_hash_move(&m) || self.is_killer_move(&m, self.killers.len()) {
+                                continue;
+                            }
+
+                            return Some(m);
+                        }
+                        None => {
+                            self.stage = Stage::BadCaptures;
+                            self.bad_captures.reverse();
+                        }
+                    }
+                }
+
+                Stage::BadCaptures => {
+                    match self.bad_captures.pop() {
+                        Some(m) => return Some(m),
+                        None => self.stage = Stage::Done,
+                    }
+                }
+
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    #[inline]
+    fn is_hash_move(&self, chess_move: &ChessMove) -> bool {
+        self.hash_move.as_ref().is_some_and(|m| m.is_same(chess_move))
+    }
+
+    /// checks the killers already validated and returned, up to `count`
+    #[inline]
+    fn is_killer_move(&self, chess_move: &ChessMove, count: usize) -> bool {
+        self.killers[..count]
+            .iter()
+            .flatten()
+            .any(|m| m.is_same(chess_move))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn picked_moves(
+        fen: &str,
+        hash_move: Option<ChessMove>,
+        killers: [Option<ChessMove>; 2],
+    ) -> (Vec<ChessMove>, Vec<ChessMove>) {
+        let mut state = ChessState::from_fen(fen);
+        let generator = MoveGenerator::new();
+
+        let mut expected: Vec<ChessMove> = Vec::new();
+        generator.generate_moves(&mut state, &mut expected);
+
+        let mut picker = MovePicker::new(hash_move, killers);
+        let mut picked: Vec<ChessMove> = Vec::new();
+
+        while let Some(m) = picker.next_move(&generator, &mut state) {
+            picked.push(m);
+        }
+
+        (picked, expected)
+    }
+
+    fn find_move(fen: &str, move_str: &str) -> ChessMove {
+        let state = ChessState::from_fen(fen);
+        ChessMove::parse(move_str, &state).unwrap()
+    }
+
+    #[test]
+    fn test_picker_yields_all_moves_once() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - ",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ];
+
+        for fen in fens.iter() {
+            let (picked, expected) = picked_moves(fen, None, [None, None]);
+
+            assert_eq!(picked.len(), expected.len(), "{}", fen);
+
+            for m in expected.iter() {
+                assert_eq!(picked.iter().filter(|p| p.is_same(m)).count(), 1, "{} {}", fen, m);
+            }
+        }
+    }
+
+    #[test]
+    fn test_picker_hash_move_and_killers() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -";
+
+        let hash_move = find_move(fen, "a2a3");
+        let killers = [Some(find_move(fen, "e1g1")), Some(find_move(fen, "b2b3"))];
+
+        let (picked, expected) = picked_moves(fen, Some(hash_move.clone()), killers);
+
+        assert_eq!(picked.len(), expected.len());
+        assert!(picked[0].is_same(&hash_move));
+
+        // killers come right after the good captures, bad captures are left for the end
+        let good_captures = picked[1..].iter().take_while(|m| m.is_capture()).count();
+        assert!(good_captures > 0);
+        assert_eq!(picked[good_captures + 1].to_string(), "King e1-g1");
+        assert_eq!(picked[good_captures + 2].to_string(), "Pawn b2-b3");
+        assert!(picked.last().unwrap().is_capture());
+    }
+
+    #[test]
+    fn test_picker_rejects_illegal_hash_move() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let other_fen = "rnbqkbnr/pppppppp/8/8/8/8/1PPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+        // a rook lift which is only possible without the a2 pawn
+        let hash_move = find_move(other_fen, "a1a3");
+
+        let (picked, expected) = picked_moves(fen, Some(hash_move.clone()), [None, None]);
+
+        assert_eq!(picked.len(), expected.len());
+        assert!(!picked.iter().any(|m| m.is_same(&hash_move)));
+    }
+
+    #[test]
+    fn test_capture_picker() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -";
+        let mut state = ChessState::from_fen(fen);
+        let generator = MoveGenerator::new();
+
+        let mut picker = MovePicker::captures();
+        let mut picked: Vec<ChessMove> = Vec::new();
+
+        while let Some(m) = picker.next_move(&generator, &mut state) {
+            picked.push(m);
+        }
+
+        // the captures losing material are left out
+        assert!(!picked.is_empty());
+        assert!(picked.iter().all(|m| m.is_capture() && generator.see(&state, m) >= 0));
+
+        let (all_moves, _) = picked_moves(fen, None, [None, None]);
+        let bad = all_moves.iter().filter(|m| m.is_capture() && generator.see(&state, m) < 0).count();
+        assert_eq!(picked.len() + bad, all_moves.iter().filter(|m| m.is_capture()).count());
+    }
+}
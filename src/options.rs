@@ -0,0 +1,357 @@
+use crate::adjudication::{DEFAULT_RESIGN_MOVES, DEFAULT_RESIGN_SCORE};
+use crate::engine::{DEFAULT_DEPTH, DEFAULT_HASH_MB, INFINITE_DEPTH, MATE_SCORE, MAX_MULTI_PV, MAX_THREADS};
+use crate::evaluator::EvalWeights;
+use crate::game_setup::GameSetup;
+use crate::logging;
+use crate::skill::{MAX_ELO, MAX_SKILL, MIN_ELO};
+
+/// Type of an option with its default value and the values allowed.
+#[derive(Debug)]
+pub enum OptionKind {
+    Check { default: bool },
+    Spin { default: i64, min: i64, max: i64 },
+    Combo { default: &'static str, values: &'static [&'static str] },
+    String { default: &'static str },
+}
+
+/// Value of an option, checked against its kind.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum OptionValue {
+    Check(bool),
+    Spin(i64),
+    Text(String),
+}
+
+impl OptionValue {
+    fn check(&self) -> bool {
+        matches!(self, OptionValue::Check(true))
+    }
+
+    fn spin(&self) -> i64 {
+        match self {
+            OptionValue::Spin(value) => *value,
+            _ => 0,
+        }
+    }
+
+    fn text(&self) -> &str {
+        match self {
+            OptionValue::Text(value) => value.as_str(),
+            _ => "",
+        }
+    }
+}
+
+/// An engine setting changed at runtime by the GUI.
+pub struct EngineOption {
+    pub name: &'static str,
+    pub kind: OptionKind,
+    /// applies a value of the right kind to the setup
+    apply: fn(&mut GameSetup, &OptionValue) -> Result<(), String>,
+}
+
+/// All options, announced to the GUI in this order.
+pub static OPTIONS: &[EngineOption] = &[
+    EngineOption {
+        name: "Threads",
+        kind: OptionKind::Spin { default: 1, min: 1, max: MAX_THREADS as i64 },
+        apply: |setup, value| {
+            setup.engine.set_threads(value.spin() as usize);
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "Hash",
+        kind: OptionKind::Spin { default: DEFAULT_HASH_MB as i64, min: 1, max: 4096 },
+        apply: |setup, value| {
+            setup.engine.set_hash_size(value.spin() as usize);
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "Ponder",
+        kind: OptionKind::Check { default: false },
+        apply: |setup, value| {
+            setup.pondering = value.check();
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "MultiPV",
+        kind: OptionKind::Spin { default: 1, min: 1, max: MAX_MULTI_PV as i64 },
+        apply: |setup, value| {
+            setup.engine.set_multi_pv(value.spin() as usize);
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "Depth",
+        kind: OptionKind::Spin { default: DEFAULT_DEPTH as i64, min: 1, max: INFINITE_DEPTH as i64 },
+        apply: |setup, value| {
+            setup.engine.params.max_depth = value.spin() as u32;
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "Skill Level",
+        kind: OptionKind::Spin { default: MAX_SKILL as i64, min: 0, max: MAX_SKILL as i64 },
+        apply: |setup, value| {
+            setup.skill_level = value.spin() as u32;
+            setup.update_skill();
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "UCI_LimitStrength",
+        kind: OptionKind::Check { default: false },
+        apply: |setup, value| {
+            setup.limit_strength = value.check();
+            setup.update_skill();
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "UCI_Elo",
+        kind: OptionKind::Spin { default: MAX_ELO as i64, min: MIN_ELO as i64, max: MAX_ELO as i64 },
+        apply: |setup, value| {
+            setup.elo = value.spin() as u32;
+            setup.update_skill();
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "Random Seed",
+        kind: OptionKind::Spin { default: 0, min: 0, max: i32::MAX as i64 },
+        apply: |setup, value| {
+            // zero picks a fresh seed, any other value replays the same games
+            match value.spin() {
+                0 => setup.engine.set_entropy_seed(),
+                seed => setup.engine.set_seed(seed as u64),
+            }
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "Pawn Value",
+        kind: OptionKind::Spin { default: EvalWeights::DEFAULT.pawn as i64, min: 0, max: 2000 },
+        apply: |setup, value| {
+            setup.engine.weights.pawn = value.spin() as i32;
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "Knight Value",
+        kind: OptionKind::Spin { default: EvalWeights::DEFAULT.knight as i64, min: 0, max: 2000 },
+        apply: |setup, value| {
+            setup.engine.weights.knight = value.spin() as i32;
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "Bishop Value",
+        kind: OptionKind::Spin { default: EvalWeights::DEFAULT.bishop as i64, min: 0, max: 2000 },
+        apply: |setup, value| {
+            setup.engine.weights.bishop = value.spin() as i32;
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "Rook Value",
+        kind: OptionKind::Spin { default: EvalWeights::DEFAULT.rook as i64, min: 0, max: 2000 },
+        apply: |setup, value| {
+            setup.engine.weights.rook = value.spin() as i32;
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "Queen Value",
+        kind: OptionKind::Spin { default: EvalWeights::DEFAULT.queen as i64, min: 0, max: 2000 },
+        apply: |setup, value| {
+            setup.engine.weights.queen = value.spin() as i32;
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "Positional Weight",
+        kind: OptionKind::Spin { default: EvalWeights::DEFAULT.positional as i64, min: 0, max: 400 },
+        apply: |setup, value| {
+            setup.engine.weights.positional = value.spin() as i32;
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "Resign Score",
+        kind: OptionKind::Spin { default: DEFAULT_RESIGN_SCORE as i64, min: 100, max: MATE_SCORE as i64 },
+        apply: |setup, value| {
+            setup.adjudicator.resign_score = value.spin() as i32;
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "Resign Moves",
+        kind: OptionKind::Spin { default: DEFAULT_RESIGN_MOVES as i64, min: 0, max: 20 },
+        apply: |setup, value| {
+            setup.adjudicator.resign_moves = value.spin() as u32;
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "Offer Draws",
+        kind: OptionKind::Check { default: true },
+        apply: |setup, value| {
+            setup.adjudicator.offer_draws = value.check();
+            Ok(())
+        },
+    },
+    EngineOption {
+        name: "Log Level",
+        kind: OptionKind::Combo {
+            default: "Info",
+            values: &["Off", "Error", "Warn", "Info", "Debug", "Trace"],
+        },
+        apply: |_, value| logging::set_level(value.text()),
+    },
+    EngineOption {
+        name: "Log File",
+        kind: OptionKind::String { default: logging::DEFAULT_LOG_FILE },
+        apply: |_, value| logging::set_file(value.text()),
+    },
+];
+
+impl EngineOption {
+    /// Checks a value given by the GUI, the check options take `true`/`false` as well as `1`/`0`.
+    pub fn parse(&self, value: &str) -> Result<OptionValue, String> {
+        let value = value.trim();
+
+        match &self.kind {
+            OptionKind::Check { .. } => match value {
+                "true" | "1" => Ok(OptionValue::Check(true)),
+                "false" | "0" => Ok(OptionValue::Check(false)),
+                _ => Err(format!("invalid value of {}: {}", self.name, value)),
+            },
+            OptionKind::Spin { min, max, .. } => match value.parse() {
+                Ok(number) if (*min..=*max).contains(&number) => Ok(OptionValue::Spin(number)),
+                _ => Err(format!("{} takes a number from {} to {}: {}", self.name, min, max, value)),
+            },
+            OptionKind::Combo { values, .. } => values
+                .iter()
+                .find(|allowed| allowed.eq_ignore_ascii_case(value))
+                .map(|allowed| OptionValue::Text(allowed.to_string()))
+                .ok_or_else(|| format!("{} takes one of {}: {}", self.name, values.join(", "), value)),
+            OptionKind::String { .. } => match value {
+                "<empty>" => Ok(OptionValue::Text(String::new())),
+                _ => Ok(OptionValue::Text(value.to_owned())),
+            },
+        }
+    }
+
+    /// The `option` line of the UCI handshake.
+    pub fn uci_declaration(&self) -> String {
+        match &self.kind {
+            OptionKind::Check { default } => format!("option name {} type check default {}", self.name, default),
+            OptionKind::Spin { default, min, max } => {
+                format!("option name {} type spin default {} min {} max {}", self.name, default, min, max)
+            }
+            OptionKind::Combo { default, values } => {
+                let vars: Vec<String> = values.iter().map(|value| format!("var {}", value)).collect();
+                format!("option name {} type combo default {} {}", self.name, default, vars.join(" "))
+            }
+            OptionKind::String { default } => format!("option name {} type string default {}", self.name, default),
+        }
+    }
+
+    /// The `feature option` line of the xboard handshake.
+    pub fn xboard_feature(&self) -> String {
+        let declaration = match &self.kind {
+            OptionKind::Check { default } => format!("{} -check {}", self.name, *default as i32),
+            OptionKind::Spin { default, min, max } => format!("{} -spin {} {} {}", self.name, default, min, max),
+            OptionKind::Combo { default, values } => {
+                let choices: Vec<String> = values
+                    .iter()
+                    .map(|value| if value == default { format!("*{}", value) } else { value.to_string() })
+                    .collect();
+                format!("{} -combo {}", self.name, choices.join(" /// "))
+            }
+            OptionKind::String { default } => format!("{} -string {}", self.name, default),
+        };
+
+        format!("feature option=\"{}\"", declaration)
+    }
+}
+
+/// The option with the given name, ignoring the case as the UCI protocol does.
+pub fn find(name: &str) -> Option<&'static EngineOption> {
+    OPTIONS.iter().find(|option| option.name.eq_ignore_ascii_case(name.trim()))
+}
+
+/// Checks the value and applies it to the setup.
+pub fn set_option(setup: &mut GameSetup, name: &str, value: &str) -> Result<(), String> {
+    let option = find(name).ok_or_else(|| format!("unknown option: {}", name))?;
+    let value = option.parse(value)?;
+
+    (option.apply)(setup, &value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_values() {
+        let threads = find("threads").unwrap();
+        assert_eq!(threads.parse("4"), Ok(OptionValue::Spin(4)));
+        assert!(threads.parse("0").is_err());
+        assert!(threads.parse("many").is_err());
+
+        let ponder = find("Ponder").unwrap();
+        assert_eq!(ponder.parse("true"), Ok(OptionValue::Check(true)));
+        assert_eq!(ponder.parse("0"), Ok(OptionValue::Check(false)));
+
+        let level = find("Log Level").unwrap();
+        assert_eq!(level.parse("debug"), Ok(OptionValue::Text("Debug".to_owned())));
+        assert!(level.parse("verbose").is_err());
+
+        assert_eq!(find("Log File").unwrap().parse("<empty>"), Ok(OptionValue::Text(String::new())));
+        assert!(find("Style").is_none());
+    }
+
+    #[test]
+    fn test_declarations() {
+        let hash = find("Hash").unwrap();
+        assert_eq!(hash.uci_declaration(), "option name Hash type spin default 16 min 1 max 4096");
+        assert_eq!(hash.xboard_feature(), "feature option=\"Hash -spin 16 1 4096\"");
+
+        let level = find("Log Level").unwrap();
+        assert_eq!(
+            level.uci_declaration(),
+            "option name Log Level type combo default Info var Off var Error var Warn var Info var Debug var Trace"
+        );
+        assert_eq!(
+            level.xboard_feature(),
+            "feature option=\"Log Level -combo Off /// Error /// Warn /// *Info /// Debug /// Trace\""
+        );
+
+        assert_eq!(find("Ponder").unwrap().xboard_feature(), "feature option=\"Ponder -check 0\"");
+    }
+
+    #[test]
+    fn test_set_option() {
+        let mut setup = GameSetup::new();
+
+        set_option(&mut setup, "Depth", "3").unwrap();
+        assert_eq!(setup.engine.params.max_depth, 3);
+
+        set_option(&mut setup, "Knight Value", "300").unwrap();
+        set_option(&mut setup, "Positional Weight", "50").unwrap();
+        assert_eq!(setup.engine.weights.knight, 300);
+        assert_eq!(setup.engine.weights.positional, 50);
+
+        set_option(&mut setup, "Random Seed", "42").unwrap();
+        set_option(&mut setup, "Random Seed", "0").unwrap();
+        assert!(set_option(&mut setup, "Random Seed", "-1").is_err());
+
+        // a rejected value leaves the setting alone
+        assert!(set_option(&mut setup, "Depth", "100").is_err());
+        assert_eq!(setup.engine.params.max_depth, 3);
+    }
+}
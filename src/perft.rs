@@ -0,0 +1,690 @@
+use std::fmt;
+use std::fs;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+
+use crate::game_setup::ChessMove;
+use crate::move_generator::MoveGenerator;
+use crate::state::ChessState;
+
+/// Counts the leaf nodes of the legal move tree of the given depth.
+pub fn perft(generator: &MoveGenerator, depth: u32, state: &mut ChessState) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let mut moves: Vec<ChessMove> = Vec::with_capacity(32);
+
+    generator.generate_moves(state, &mut moves);
+
+    let mut result = 0;
+
+    for m in moves.iter() {
+        state.do_move(m);
+        result += perft(generator, depth - 1, state);
+        state.undo_move(m);
+    }
+
+    result
+}
+
+/// Perft split by the root moves, in move generation order.
+pub fn divide(generator: &MoveGenerator, depth: u32, state: &mut ChessState) -> Vec<(ChessMove, u64)> {
+    let mut moves: Vec<ChessMove> = Vec::with_capacity(32);
+
+    if depth == 0 {
+        return Vec::new();
+    }
+
+    generator.generate_moves(state, &mut moves);
+
+    moves
+        .into_iter()
+        .map(|m| {
+            state.do_move(&m);
+            let count = perft(generator, depth - 1, state);
+            state.undo_move(&m);
+
+            (m, count)
+        })
+        .collect()
+}
+
+/// Switches for the optimized perft, the counts are the same as with `perft`.
+#[derive(Debug, Clone)]
+pub struct PerftOptions {
+    /// count the legal moves at depth 1 without making them
+    pub bulk_counting: bool,
+    /// number of perft hash table entries, split between the threads, 0 disables the table
+    pub hash_entries: usize,
+    /// number of threads splitting the root moves
+    pub threads: usize,
+}
+
+impl Default for PerftOptions {
+    fn default() -> Self {
+        PerftOptions {
+            bulk_counting: true,
+            hash_entries: 0,
+            threads: 1,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+struct PerftEntry {
+    key: u64,
+    depth: u32,
+    count: u64,
+}
+
+/// Always-replace table of subtree counts keyed by Zobrist key and depth.
+pub struct PerftHash {
+    entries: Vec<PerftEntry>,
+}
+
+impl PerftHash {
+    pub fn new(size: usize) -> PerftHash {
+        PerftHash {
+            entries: vec![PerftEntry::default(); size],
+        }
+    }
+
+    /// Number of entries for a table of the given size in megabytes.
+    pub fn entries_for_mb(megabytes: usize) -> usize {
+        megabytes * 1024 * 1024 / std::mem::size_of::<PerftEntry>()
+    }
+
+    #[inline]
+    fn get(&self, key: u64, depth: u32) -> Option<u64> {
+        let entry = &self.entries[(key % self.entries.len() as u64) as usize];
+
+        if entry.key == key && entry.depth == depth {
+            Some(entry.count)
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    fn store(&mut self, key: u64, depth: u32, count: u64) {
+        let len = self.entries.len() as u64;
+        self.entries[(key % len) as usize] = PerftEntry { key, depth, count };
+    }
+}
+
+/// Perft with leaf bulk counting and an optional hash table.
+pub fn perft_hashed(
+    generator: &MoveGenerator,
+    depth: u32,
+    state: &mut ChessState,
+    bulk_counting: bool,
+    hash: &mut Option<PerftHash>,
+) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    if let Some(count) = hash.as_ref().and_then(|h| h.get(state.hash_key(), depth)) {
+        return count;
+    }
+
+    let mut moves: Vec<ChessMove> = Vec::with_capacity(32);
+
+    generator.generate_moves(state, &mut moves);
+
+    let result = if depth == 1 && bulk_counting {
+        moves.len() as u64
+    } else {
+        let mut result = 0;
+
+        for m in moves.iter() {
+            state.do_move(m);
+            result += perft_hashed(generator, depth - 1, state, bulk_counting, hash);
+            state.undo_move(m);
+        }
+
+        result
+    };
+
+    if let Some(hash) = hash.as_mut() {
+        hash.store(state.hash_key(), depth, result);
+    }
+
+    result
+}
+
+/// Divide using the perft options, with the root moves split between threads.
+pub fn divide_with(
+    generator: &MoveGenerator,
+    depth: u32,
+    state: &mut ChessState,
+    options: &PerftOptions,
+) -> Vec<(ChessMove, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+
+    let mut moves: Vec<ChessMove> = Vec::with_capacity(32);
+    generator.generate_moves(state, &mut moves);
+
+    // each thread gets its own table with a share of the entries
+    let hash_entries = match options.hash_entries {
+        0 => 0,
+        entries => (entries / options.threads.max(1)).max(1),
+    };
+
+    let new_hash = || {
+        if hash_entries > 0 {
+            Some(PerftHash::new(hash_entries))
+        } else {
+            None
+        }
+    };
+
+    if options.threads <= 1 {
+        let mut hash = new_hash();
+
+        return moves
+            .into_iter()
+            .map(|m| {
+                state.do_move(&m);
+                let count = perft_hashed(generator, depth - 1, state, options.bulk_counting, &mut hash);
+                state.undo_move(&m);
+
+                (m, count)
+            })
+            .collect();
+    }
+
+    // threads take the next root move until none is left
+    let next_move = AtomicUsize::new(0);
+    let counts: Vec<AtomicU64> = moves.iter().map(|_| AtomicU64::new(0)).collect();
+
+    thread::scope(|scope| {
+        for _ in 0..options.threads {
+            let mut state = state.clone();
+            let (moves, counts, next_move) = (&moves, &counts, &next_move);
+            let mut hash = new_hash();
+
+            scope.spawn(move || {
+                loop {
+                    let idx = next_move.fetch_add(1, Ordering::Relaxed);
+
+                    if idx >= moves.len() {
+                        break;
+                    }
+
+                    state.do_move(&moves[idx]);
+                    let count = perft_hashed(generator, depth - 1, &mut state, options.bulk_counting, &mut hash);
+                    state.undo_move(&moves[idx]);
+
+                    counts[idx].store(count, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+
+    moves
+        .into_iter()
+        .zip(counts.iter().map(|c| c.load(Ordering::Relaxed)))
+        .collect()
+}
+
+/// Formats divide results the way other engines print them: `e2e4: 20`, one move per line.
+pub fn format_divide(divide: &[(ChessMove, u64)]) -> String {
+    let mut result = String::new();
+
+    for (m, count) in divide.iter() {
+        result.push_str(format!("{}: {}\n", m.to_coord_string(), count).as_str());
+    }
+
+    result
+}
+
+/// Lists the moves whose counts differ from the reference divide, which uses the
+/// `e2e4: 20` format of `format_divide` and of the other engines.
+pub fn diff_divide(divide: &[(ChessMove, u64)], reference: &str) -> String {
+    let mut expected: Vec<(String, u64)> = Vec::new();
+
+    for line in reference.lines() {
+        if let Some((name, count)) = line.split_once(':') {
+            if let Ok(count) = count.trim().parse() {
+                expected.push((name.trim().to_string(), count));
+            }
+        }
+    }
+
+    let mut result = String::new();
+
+    for (m, count) in divide.iter() {
+        let name = m.to_coord_string();
+
+        match expected.iter().find(|(e, _)| *e == name) {
+            Some((_, e)) if e == count => {}
+            Some((_, e)) => result.push_str(format!("{}: {} expected {}\n", name, count, e).as_str()),
+            None => result.push_str(format!("{}: {} not expected\n", name, count).as_str()),
+        }
+    }
+
+    for (name, count) in expected.iter() {
+        if !divide.iter().any(|(m, _)| m.to_coord_string() == *name) {
+            result.push_str(format!("{}: missing, expected {}\n", name, count).as_str());
+        }
+    }
+
+    result
+}
+
+/// A position of the perft suite with its expected counts per depth.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuitePosition {
+    pub fen: String,
+    pub depths: Vec<(u32, u64)>,
+    /// reference divides per depth, in the `format_divide` format
+    pub references: Vec<(u32, String)>,
+}
+
+/// Parses perft suite lines in the EPD format `FEN ;D1 20 ;D2 400 ...`, where entries
+/// such as `;D2 e2e4 20` give the reference divide of a depth move by move.
+/// Empty lines and lines starting with `#` are skipped.
+pub fn parse_epd(text: &str) -> Result<Vec<SuitePosition>, String> {
+    let mut result = Vec::new();
+
+    for (line_idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.split(';');
+        let fen = parts.next().unwrap_or("").trim().to_string();
+        let mut depths = Vec::new();
+        let mut references: Vec<(u32, String)> = Vec::new();
+
+        for part in parts {
+            let invalid = || format!("line {}: invalid perft entry: {}", line_idx + 1, part.trim());
+
+            let mut tokens = part.split_whitespace();
+            let depth = tokens
+                .next()
+                .and_then(|token| token.strip_prefix('D'))
+                .and_then(|depth| depth.parse::<u32>().ok())
+                .ok_or_else(invalid)?;
+
+            match tokens.collect::<Vec<&str>>().as_slice() {
+                [count] => depths.push((depth, count.parse().map_err(|_| invalid())?)),
+                [name, count] => {
+                    let count: u64 = count.parse().map_err(|_| invalid())?;
+                    let line = format!("{}: {}\n", name, count);
+
+                    match references.iter_mut().find(|(d, _)| *d == depth) {
+                        Some((_, divide)) => divide.push_str(line.as_str()),
+                        None => references.push((depth, line)),
+                    }
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        result.push(SuitePosition { fen, depths, references });
+    }
+
+    Ok(result)
+}
+
+/// Limits for a suite run: depths above `max_depth` and counts above `max_nodes` are skipped.
+#[derive(Debug, Clone)]
+pub struct SuiteBudget {
+    pub max_depth: u32,
+    pub max_nodes: u64,
+}
+
+impl Default for SuiteBudget {
+    fn default() -> Self {
+        SuiteBudget {
+            max_depth: u32::MAX,
+            max_nodes: u64::MAX,
+        }
+    }
+}
+
+/// A suite position whose perft count differs from the expected one.
+#[derive(Debug)]
+pub struct SuiteMismatch {
+    pub fen: String,
+    pub depth: u32,
+    pub expected: u64,
+    pub found: u64,
+    /// divide of the shallowest failing depth
+    pub divide: Vec<(ChessMove, u64)>,
+    /// reference divide of that depth, when the suite gives one
+    pub reference: Option<String>,
+}
+
+impl fmt::Display for SuiteMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.fen)?;
+        writeln!(f, "depth {}: expected {}, found {}", self.depth, self.expected, self.found)?;
+
+        match &self.reference {
+            Some(reference) => write!(f, "{}", diff_divide(&self.divide, reference)),
+            None => write!(f, "{}", format_divide(&self.divide)),
+        }
+    }
+}
+
+/// Runs the suite positions within the budget and returns the mismatches,
+/// at most one per position.
+pub fn run_suite(
+    generator: &MoveGenerator,
+    positions: &[SuitePosition],
+    budget: &SuiteBudget,
+    options: &PerftOptions,
+) -> Vec<SuiteMismatch> {
+    let mut result = Vec::new();
+
+    for position in positions.iter() {
+        let mut state = ChessState::from_fen(position.fen.as_str());
+
+        let mut depths: Vec<(u32, u64)> = position
+            .depths
+            .iter()
+            .filter(|(depth, count)| *depth <= budget.max_depth && *count <= budget.max_nodes)
+            .cloned()
+            .collect();
+
+        depths.sort();
+
+        for (depth, expected) in depths {
+            let divide = divide_with(generator, depth, &mut state, options);
+            let found = if depth == 0 { 1 } else { divide.iter().map(|(_, count)| count).sum() };
+
+            if found != expected {
+                result.push(SuiteMismatch {
+                    fen: position.fen.clone(),
+                    depth,
+                    expected,
+                    found,
+                    divide,
+                    reference: position
+                        .references
+                        .iter()
+                        .find(|(d, _)| *d == depth)
+                        .map(|(_, reference)| reference.clone()),
+                });
+                break;
+            }
+        }
+    }
+
+    result
+}
+
+const USAGE: &str = "usage: chessbot perft [--fen FEN] --depth N [--divide] [--reference FILE] [--no-bulk] [--hash MB] [--threads N]
+       chessbot perft --suite FILE [--max-depth N] [--max-nodes N] [--no-bulk] [--hash MB] [--threads N]
+       --hash MB is the total table size, split between the --threads";
+
+/// Runs the `perft` command line subcommand, either for one position or for a suite file:
+/// `chessbot perft [--fen FEN] --depth N [--divide] [--reference FILE] [--no-bulk] [--hash MB] [--threads N]`
+/// `chessbot perft --suite FILE [--max-depth N] [--max-nodes N] ...`
+pub fn run_command(args: &[String]) -> Result<(), String> {
+    let mut fen: Option<String> = None;
+    let mut depth: Option<u32> = None;
+    let mut divide_moves = false;
+    let mut reference: Option<String> = None;
+    let mut suite: Option<String> = None;
+    let mut budget = SuiteBudget::default();
+    let mut options = PerftOptions::default();
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fen" => {
+                fen = Some(args.next().ok_or("missing value for --fen")?.clone());
+            }
+            "--depth" => {
+                let value = args.next().ok_or("missing value for --depth")?;
+                depth = Some(value.parse().map_err(|_| format!("invalid depth: {}", value))?);
+            }
+            "--divide" => divide_moves = true,
+            "--reference" => {
+                let path = args.next().ok_or("missing value for --reference")?;
+                reference = Some(fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?);
+            }
+            "--suite" => {
+                suite = Some(args.next().ok_or("missing value for --suite")?.clone());
+            }
+            "--max-depth" => {
+                let value = args.next().ok_or("missing value for --max-depth")?;
+                budget.max_depth = value.parse().map_err(|_| format!("invalid depth: {}", value))?;
+            }
+            "--max-nodes" => {
+                let value = args.next().ok_or("missing value for --max-nodes")?;
+                budget.max_nodes = value.parse().map_err(|_| format!("invalid node count: {}", value))?;
+            }
+            "--no-bulk" => options.bulk_counting = false,
+            "--hash" => {
+                let value = args.next().ok_or("missing value for --hash")?;
+                let megabytes = value.parse().map_err(|_| format!("invalid hash size: {}", value))?;
+                options.hash_entries = PerftHash::entries_for_mb(megabytes);
+            }
+            "--threads" => {
+                let value = args.next().ok_or("missing value for --threads")?;
+                options.threads = value.parse().map_err(|_| format!("invalid thread count: {}", value))?;
+            }
+            _ => return Err(format!("unknown perft argument: {}", arg)),
+        }
+    }
+
+    if let Some(path) = suite {
+        return run_suite_command(path.as_str(), &budget, &options);
+    }
+
+    let depth = depth.ok_or(USAGE)?;
+
+    let mut state = match fen {
+        Some(fen) => ChessState::from_fen(fen.as_str()),
+        None => ChessState::new_game(),
+    };
+
+    let generator = MoveGenerator::new();
+
+    let now = Instant::now();
+
+    let result = divide_with(&generator, depth, &mut state, &options);
+
+    if divide_moves {
+        print!("{}", format_divide(&result));
+        println!();
+    }
+
+    if let Some(reference) = reference {
+        let diff = diff_divide(&result, reference.as_str());
+
+        if diff.is_empty() {
+            println!("Divide matches the reference");
+        } else {
+            print!("Divide differs from the reference:\n{}", diff);
+        }
+        println!();
+    }
+
+    let nodes = if depth == 0 {
+        1
+    } else {
+        result.iter().map(|(_, count)| count).sum()
+    };
+
+    let elapsed = now.elapsed();
+    let nps = (nodes as f64 / elapsed.as_secs_f64().max(1e-9)) as u64;
+
+    println!("Nodes searched: {}", nodes);
+    println!("Time: {} ms", elapsed.as_millis());
+    println!("NPS: {}", nps);
+
+    Ok(())
+}
+
+fn run_suite_command(path: &str, budget: &SuiteBudget, options: &PerftOptions) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let positions = parse_epd(text.as_str())?;
+
+    let generator = MoveGenerator::new();
+    let now = Instant::now();
+
+    let mismatches = run_suite(&generator, &positions, budget, options);
+
+    for mismatch in mismatches.iter() {
+        println!("{}", mismatch);
+    }
+
+    println!(
+        "Positions: {}, failed: {}, time: {} ms",
+        positions.len(),
+        mismatches.len(),
+        now.elapsed().as_millis()
+    );
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} perft suite positions failed", mismatches.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_divide() {
+        let generator = MoveGenerator::new();
+        let mut state = ChessState::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -");
+
+        let result = divide(&generator, 2, &mut state);
+
+        assert_eq!(result.len(), 48);
+        assert_eq!(result.iter().map(|(_, count)| count).sum::<u64>(), 2039);
+
+        let text = format_divide(&result);
+        assert!(text.contains("e1g1: 43\n"));
+        assert!(text.contains("e5f7: 44\n"));
+        assert!(text.contains("d5e6: 46\n"));
+    }
+
+    #[test]
+    fn test_fast_perft_matches_serial() {
+        let generator = MoveGenerator::new();
+
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - ",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ];
+
+        let option_sets = [
+            PerftOptions { bulk_counting: false, hash_entries: 0, threads: 1 },
+            PerftOptions { bulk_counting: true, hash_entries: 0, threads: 1 },
+            PerftOptions { bulk_counting: true, hash_entries: 4096, threads: 1 },
+            PerftOptions { bulk_counting: false, hash_entries: 1024, threads: 3 },
+        ];
+
+        for fen in fens.iter() {
+            let mut state = ChessState::from_fen(fen);
+            let expected = divide(&generator, 3, &mut state);
+
+            for options in option_sets.iter() {
+                let result = divide_with(&generator, 3, &mut state, options);
+
+                assert_eq!(format_divide(&result), format_divide(&expected), "{} {:?}", fen, options);
+            }
+
+            assert_eq!(state, ChessState::from_fen(fen));
+        }
+    }
+
+    #[test]
+    fn test_parse_epd() {
+        let text = "# comment\n\n4k3/8/8/8/8/8/8/4K2R w K - 0 1 ;D1 15 ;D2 66\n";
+
+        let positions = parse_epd(text).unwrap();
+
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].fen, "4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+        assert_eq!(positions[0].depths, vec![(1, 15), (2, 66)]);
+        assert!(positions[0].references.is_empty());
+
+        let positions = parse_epd("4k3/8/8/8/8/8/8/4K2R w K - 0 1 ;D1 15 ;D1 e1f1 1 ;D1 e1g1 1").unwrap();
+        assert_eq!(positions[0].references, vec![(1, "e1f1: 1\ne1g1: 1\n".to_owned())]);
+
+        assert!(parse_epd("4k3/8/8/8/8/8/8/4K2R w K - 0 1 ;D1 x").is_err());
+        assert!(parse_epd("4k3/8/8/8/8/8/8/4K2R w K - 0 1 ;X1 15").is_err());
+    }
+
+    #[test]
+    fn test_perft_suite() {
+        // the budget keeps the debug test run short, raise it with PERFT_SUITE_NODES
+        let max_nodes = std::env::var("PERFT_SUITE_NODES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(200_000);
+
+        let positions = parse_epd(include_str!("../perftsuite.epd")).unwrap();
+        let budget = SuiteBudget { max_depth: u32::MAX, max_nodes };
+
+        let generator = MoveGenerator::new();
+        let mismatches = run_suite(&generator, &positions, &budget, &PerftOptions::default());
+
+        let report: Vec<String> = mismatches.iter().map(|m| m.to_string()).collect();
+        assert!(mismatches.is_empty(), "\n{}", report.join("\n"));
+    }
+
+    #[test]
+    fn test_suite_mismatch_report() {
+        let generator = MoveGenerator::new();
+        let positions = parse_epd("4k3/8/8/8/8/8/8/4K2R w K - 0 1 ;D1 15 ;D2 67 ;D3 1197").unwrap();
+
+        let mismatches = run_suite(&generator, &positions, &SuiteBudget::default(), &PerftOptions::default());
+
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].depth, 2);
+        assert_eq!(mismatches[0].found, 66);
+
+        let (first, count) = &mismatches[0].divide[0];
+        let first = first.to_coord_string();
+
+        // the reference has one more node after the first move and an extra move
+        let mut reference = format_divide(&mismatches[0].divide[1..]);
+        reference.push_str(format!("{}: {}\ne1c1: 1\n", first, count + 1).as_str());
+
+        let diff = diff_divide(&mismatches[0].divide, reference.as_str());
+        assert_eq!(diff, format!("{}: {} expected {}\ne1c1: missing, expected 1\n", first, count, count + 1));
+
+        // with the reference divide in the suite line the report shows the difference
+        let mut line = "4k3/8/8/8/8/8/8/4K2R w K - 0 1 ;D2 67".to_owned();
+        for entry in reference.lines() {
+            line.push_str(format!(" ;D2 {}", entry.replace(':', "")).as_str());
+        }
+
+        let positions = parse_epd(line.as_str()).unwrap();
+        let mismatches = run_suite(&generator, &positions, &SuiteBudget::default(), &PerftOptions::default());
+        assert!(mismatches[0].to_string().ends_with(diff.as_str()));
+    }
+
+    #[test]
+    fn test_run_command_arguments() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+
+        assert!(run_command(&args(&["--fen"])).is_err());
+        assert!(run_command(&args(&["--depth", "x"])).is_err());
+        assert!(run_command(&args(&["--divide"])).is_err());
+        assert!(run_command(&args(&["--depth", "1", "--threads", "x"])).is_err());
+        assert!(run_command(&args(&["--suite", "missing.epd"])).is_err());
+        assert!(run_command(&args(&["--depth", "1", "--divide"])).is_ok());
+        assert!(run_command(&args(&["--depth", "2", "--hash", "1", "--threads", "2", "--no-bulk"])).is_ok());
+    }
+}
@@ -1,5 +1,8 @@
 use crate::bboard::BBoard;
 
+/// Move tables computed at compile time.
+static PIECE_MOVES: PieceMoveProvider = PieceMoveProvider::new();
+
 pub struct PieceMoveProvider {
     pub black_pawn_move: [BBoard; 64],
     pub black_pawn_capture: [BBoard; 64],
@@ -17,47 +20,71 @@ pub struct PieceMoveProvider {
     pub inner_bishop_attack_bits: [BBoard; 64],
 }
 
-impl PieceMoveProvider {
-    pub fn new() -> PieceMoveProvider {
-        let mut result = PieceMoveProvider {
-            black_pawn_move: generate_moves(&black_pawn_move),
-            black_pawn_capture: generate_moves(&black_pawn_capture),
-            white_pawn_move: generate_moves(&white_pawn_move),
-            white_pawn_capture: generate_moves(&white_pawn_capture),
+impl Default for PieceMoveProvider {
+    fn default() -> Self {
+        PieceMoveProvider::new()
+    }
+}
 
-            rook_move: generate_moves(&rook_move),
-            knight_move: generate_moves(&knight_move),
-            bishop_move: generate_moves(&bishop_move),
-            queen_move: generate_moves(&queen_move),
-            king_move: generate_moves(&king_move),
+/// Fills a table with the moves of the given function for every square.
+macro_rules! generate_moves {
+    ($func:ident) => {{
+        let mut result: [BBoard; 64] = [0u64; 64];
+        let mut i = 0u8;
 
-            inner_rook_attack_bits: [0; 64],
-            inner_bishop_attack_bits: [0; 64],
-        };
+        while i < 64 {
+            result[i as usize] = $func(i);
+            i += 1;
+        }
 
-        let inner = inner_bits(&result.bishop_move, &result.rook_move);
+        result
+    }};
+}
 
-        result.inner_bishop_attack_bits = inner.0;
-        result.inner_rook_attack_bits = inner.1;
+impl PieceMoveProvider {
+    /// Process-wide move tables shared by all threads.
+    pub fn shared() -> &'static PieceMoveProvider {
+        &PIECE_MOVES
+    }
 
-        result
+    pub const fn new() -> PieceMoveProvider {
+        let bishop_move = generate_moves!(bishop_move);
+        let rook_move = generate_moves!(rook_move);
+        let inner = inner_bits(&bishop_move, &rook_move);
+
+        PieceMoveProvider {
+            black_pawn_move: generate_moves!(black_pawn_move),
+            black_pawn_capture: generate_moves!(black_pawn_capture),
+            white_pawn_move: generate_moves!(white_pawn_move),
+            white_pawn_capture: generate_moves!(white_pawn_capture),
+
+            rook_move,
+            knight_move: generate_moves!(knight_move),
+            bishop_move,
+            queen_move: generate_moves!(queen_move),
+            king_move: generate_moves!(king_move),
+
+            inner_rook_attack_bits: inner.1,
+            inner_bishop_attack_bits: inner.0,
+        }
     }
 }
 
 /* helpful boards */
-static LEFT_BORDER: BBoard = 0x0101010101010101u64;
-static RIGHT_BORDER: BBoard = 0x8080808080808080u64;
-static TOP_BORDER: BBoard = 0xff00000000000000u64;
-static BOTTOM_BORDER: BBoard = 0x00000000000000ffu64;
+const LEFT_BORDER: BBoard = 0x0101010101010101u64;
+const RIGHT_BORDER: BBoard = 0x8080808080808080u64;
+const TOP_BORDER: BBoard = 0xff00000000000000u64;
+const BOTTOM_BORDER: BBoard = 0x00000000000000ffu64;
 
-fn inner_bits(
+const fn inner_bits(
     bishop_moves: &[BBoard; 64],
     rook_moves: &[BBoard; 64],
 ) -> ([BBoard; 64], [BBoard; 64]) {
     let mut r_bishop = [0; 64];
     let mut r_rook = [0; 64];
 
-    for i in 0usize..64 {
+    let mut i = 0usize;
+    while i < 64 {
         let mut bboard = bishop_moves[i];
         let mut rboard = rook_moves[i];
         let x = i % 8;
@@ -85,22 +112,14 @@ fn inner_bits(
 
         r_rook[i] = rboard;
         r_bishop[i] = bboard;
-    }
-
-    (r_bishop, r_rook)
-}
 
-fn generate_moves(func: &dyn Fn(u8) -> BBoard) -> [BBoard; 64] {
-    let mut result: [BBoard; 64] = [0u64; 64];
-
-    for i in 0u8..64 {
-        result[i as usize] = func(i);
+        i += 1;
     }
 
-    result
+    (r_bishop, r_rook)
 }
 
-fn black_pawn_move(idx: u8) -> BBoard {
+const fn black_pawn_move(idx: u8) -> BBoard {
     if idx < 8 {
         return 0u64;
     }
@@ -113,14 +132,14 @@ fn black_pawn_move(idx: u8) -> BBoard {
     result
 }
 
-fn black_pawn_capture(idx: u8) -> BBoard {
+const fn black_pawn_capture(idx: u8) -> BBoard {
     if idx < 8 {
         return 0u64;
     }
 
     let mut result = 0;
 
-    if idx % 8 > 0 {
+    if !idx.is_multiple_of(8) {
         result |= 1u64 << (idx - 9) as u64
     }
     if idx % 8 < 7 {
@@ -130,7 +149,7 @@ fn black_pawn_capture(idx: u8) -> BBoard {
     result
 }
 
-fn white_pawn_move(idx: u8) -> BBoard {
+const fn white_pawn_move(idx: u8) -> BBoard {
     if idx > 55 {
         return 0u64;
     }
@@ -144,14 +163,14 @@ fn white_pawn_move(idx: u8) -> BBoard {
     result
 }
 
-fn white_pawn_capture(idx: u8) -> BBoard {
+const fn white_pawn_capture(idx: u8) -> BBoard {
     if idx > 55 {
         return 0u64;
     }
 
     let mut result = 0;
 
-    if idx % 8 > 0 {
+    if !idx.is_multiple_of(8) {
         result |= 1u64 << (idx + 7) as u64
     }
     if idx % 8 < 7 {
@@ -161,108 +180,84 @@ fn white_pawn_capture(idx: u8) -> BBoard {
     result
 }
 
-fn rook_move(idx: u8) -> BBoard {
+const fn rook_move(idx: u8) -> BBoard {
     let x1 = idx % 8;
     let y1 = idx / 8;
 
     let mut result = 0;
 
-    for i in 0..64 {
-        if idx == i {
-            continue;
-        }
-
+    let mut i = 0u8;
+    while i < 64 {
         let x2 = i % 8;
         let y2 = i / 8;
 
-        if x1 == x2 || y1 == y2 {
+        if idx != i && (x1 == x2 || y1 == y2) {
             result |= 1u64 << i as u64
         }
+
+        i += 1;
     }
 
     result
 }
 
-fn bishop_move(idx: u8) -> BBoard {
+const fn bishop_move(idx: u8) -> BBoard {
     let x1 = (idx % 8) as i32;
     let y1 = (idx / 8) as i32;
 
     let mut result = 0u64;
 
-    for i in 0..64 {
-        if idx == i {
-            continue;
-        }
-
+    let mut i = 0u8;
+    while i < 64 {
         let x2 = (i % 8) as i32;
         let y2 = (i / 8) as i32;
 
-        if x1 - y1 == x2 - y2 || x1 + y1 == x2 + y2 {
+        if idx != i && (x1 - y1 == x2 - y2 || x1 + y1 == x2 + y2) {
             result |= 1u64 << i as u64
         }
+
+        i += 1;
     }
 
     result
 }
 
-fn queen_move(idx: u8) -> BBoard {
-    let x1 = (idx % 8) as i32;
-    let y1 = (idx / 8) as i32;
-
-    let mut result = 0u64;
-
-    for i in 0..64 {
-        if idx == i {
-            continue;
-        }
-
-        let x2 = (i % 8) as i32;
-        let y2 = (i / 8) as i32;
-
-        if x1 == x2 || y1 == y2 || x1 - y1 == x2 - y2 || x1 + y1 == x2 + y2 {
-            result |= 1u64 << i as u64
-        }
-    }
-
-    result
+const fn queen_move(idx: u8) -> BBoard {
+    rook_move(idx) | bishop_move(idx)
 }
 
-fn king_move(idx: u8) -> BBoard {
+const fn king_move(idx: u8) -> BBoard {
     let x1 = (idx % 8) as i32;
     let y1 = (idx / 8) as i32;
 
     let mut result = 0u64;
 
-    for i in 0..64 {
-        if idx == i {
-            continue;
-        }
-
+    let mut i = 0u8;
+    while i < 64 {
         let x2 = (i % 8) as i32;
         let y2 = (i / 8) as i32;
 
         let dx = (x1 - x2).abs();
         let dy = (y1 - y2).abs();
 
-        if dx < 2 && dy < 2 {
+        if idx != i && dx < 2 && dy < 2 {
             result |= 1u64 << i as u64
         }
+
+        i += 1;
     }
 
     result
 }
 
-fn knight_move(idx: u8) -> BBoard {
+const fn knight_move(idx: u8) -> BBoard {
     let x1 = (idx % 8) as i32;
     let y1 = (idx / 8) as i32;
 
     let mut result = 0u64;
 
-    for i in 0..64 {
-        if idx == i {
-            continue;
-        }
-
+    let mut i = 0u8;
+    while i < 64 {
         let x2 = (i % 8) as i32;
         let y2 = (i / 8) as i32;
 
@@ -272,7 +267,58 @@ fn knight_move(idx: u8) -> BBoard {
         if dx + dy == 3 && dx > 0 && dy > 0 {
             result |= 1u64 << i as u64
         }
+
+        i += 1;
     }
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table_snapshot(table: &[BBoard; 64]) -> u64 {
+        table
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325u64, |hash, b| (hash ^ b).wrapping_mul(0x0100_0000_01b3))
+    }
+
+    #[test]
+    fn test_move_tables() {
+        let provider = PieceMoveProvider::shared();
+
+        let tables = [
+            &provider.black_pawn_move,
+            &provider.black_pawn_capture,
+            &provider.white_pawn_move,
+            &provider.white_pawn_capture,
+            &provider.rook_move,
+            &provider.knight_move,
+            &provider.bishop_move,
+            &provider.queen_move,
+            &provider.king_move,
+            &provider.inner_rook_attack_bits,
+            &provider.inner_bishop_attack_bits,
+        ];
+
+        let snapshots: Vec<u64> = tables.iter().map(|t| table_snapshot(t)).collect();
+
+        assert_eq!(
+            snapshots,
+            vec![
+                0xcfbf5b895b29d0f6,
+                0x3f09163c3db961c6,
+                0x020ba7cf62cb9125,
+                0x93ae39a3a02a9925,
+                0xed9829977ba061a1,
+                0x4aa3570ca1c60201,
+                0x6c9f3fa8df8aa862,
+                0xa3b8c411008a19fe,
+                0xffed7993989ae3c2,
+                0xf540e2101e10a6a3,
+                0x4c68daa23df0a625,
+            ]
+        );
+    }
+}
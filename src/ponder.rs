@@ -0,0 +1,110 @@
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use crate::engine::ChessEngine;
+use crate::game_setup::ChessMove;
+use crate::messaging::poll_message;
+use crate::state::ChessState;
+
+/// how long to wait for a command before checking the search again
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// What to do with a command received while searching in the background.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum InputAction {
+    /// the command has been handled, keep searching
+    Ignore,
+    /// let the search run to its end and take its move, without reading further commands
+    Finish,
+    /// stop the search and hand the command back to the caller
+    Abort,
+}
+
+/// Result of a background search.
+pub struct Background {
+    /// best move of the search, or of its last iteration when it was stopped
+    pub best_move: Option<ChessMove>,
+    /// the command which aborted the search, still to be handled
+    pub pending: Option<(String, String)>,
+}
+
+/// Searches the position on a separate thread while passing the incoming commands to `handle`.
+/// With `wait_for_input` the search result is only returned once a command asks to finish
+/// or abort, as when pondering until the opponent moves.
+pub fn search_in_background<F>(
+    engine: &mut ChessEngine,
+    state: &ChessState,
+    wait_for_input: bool,
+    mut handle: F,
+) -> Background
+where
+    F: FnMut(&str, &str) -> InputAction,
+{
+    let stop = engine.stop_flag();
+    let mut state = state.clone();
+
+    let mut pending = None;
+    let mut reading = true;
+
+    let best_move = thread::scope(|scope| {
+        let search = scope.spawn(|| engine.find_best_move(&mut state));
+
+        while reading && (wait_for_input || !search.is_finished()) {
+            let input = match poll_message(POLL_INTERVAL) {
+                Some(input) => input,
+                None => continue,
+            };
+
+            match handle(input.0.as_str(), input.1.as_str()) {
+                InputAction::Ignore => {}
+                InputAction::Finish => reading = false,
+                InputAction::Abort => {
+                    stop.store(true, Ordering::Relaxed);
+                    pending = Some(input);
+                    reading = false;
+                }
+            }
+        }
+
+        search.join().unwrap()
+    });
+
+    // a stop arriving after the search has returned must not cut the next one short
+    stop.store(false, Ordering::Relaxed);
+
+    Background { best_move, pending }
+}
+
+/// The position expected after the reply to our move, with the expected reply,
+/// or `None` when the last search left no reply to ponder on.
+pub fn ponder_position(engine: &ChessEngine, state: &ChessState) -> Option<(ChessMove, ChessState)> {
+    let mut ponder_state = state.clone();
+    let expected = engine.expected_reply(&mut ponder_state)?;
+
+    ponder_state.do_move(&expected);
+
+    Some((expected, ponder_state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ponder_position() {
+        let mut engine = ChessEngine::new();
+        let mut state = ChessState::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 b - - 0 1");
+
+        // nothing searched yet
+        assert!(ponder_position(&engine, &state).is_none());
+
+        // black saves the queen, then white is expected to move on
+        let best = engine.find_best_move(&mut state).unwrap();
+        state.do_move(&best);
+
+        let (expected, ponder_state) = ponder_position(&engine, &state).unwrap();
+        assert_eq!(expected.side, state.next_to_move);
+        assert_eq!(ponder_state.next_to_move, best.side);
+    }
+}
@@ -0,0 +1,143 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::time::Duration;
+
+/// Increments a search statistics counter, unless the counters are compiled out
+/// by building without the `search-stats` feature.
+macro_rules! count {
+    ($counter:expr) => {
+        #[cfg(feature = "search-stats")]
+        {
+            $counter += 1;
+        }
+    };
+}
+
+pub(crate) use count;
+
+/// Counters collected by the search threads during one search.
+#[derive(Clone, Default, Debug)]
+pub struct SearchStats {
+    /// positions visited, including the quiescence search
+    pub nodes: u64,
+    pub quiescence_nodes: u64,
+
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+
+    /// beta cutoffs, and the ones made by the first move searched
+    pub cutoffs: u64,
+    pub first_move_cutoffs: u64,
+
+    /// nodes of each iteration of the main thread
+    pub iteration_nodes: Vec<u64>,
+
+    pub time: Duration,
+}
+
+impl SearchStats {
+    /// Adds the counters of a helper thread, the iterations and time are the main thread ones.
+    pub fn merge(&mut self, other: &SearchStats) {
+        self.nodes += other.nodes;
+        self.quiescence_nodes += other.quiescence_nodes;
+        self.tt_probes += other.tt_probes;
+        self.tt_hits += other.tt_hits;
+        self.cutoffs += other.cutoffs;
+        self.first_move_cutoffs += other.first_move_cutoffs;
+    }
+
+    pub fn nps(&self) -> u64 {
+        let micros = self.time.as_micros().max(1) as u64;
+        self.nodes.saturating_mul(1_000_000) / micros
+    }
+
+    /// Growth of the node count from the second last iteration to the last one.
+    pub fn effective_branching_factor(&self) -> Option<f64> {
+        match self.iteration_nodes.as_slice() {
+            [.., previous, last] if *previous > 0 => Some(*last as f64 / *previous as f64),
+            _ => None,
+        }
+    }
+
+    fn percent(part: u64, total: u64) -> f64 {
+        if total == 0 {
+            0.0
+        } else {
+            part as f64 * 100.0 / total as f64
+        }
+    }
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "nodes: {}, quiescence: {:.1}%, time: {} ms, nps: {}",
+            self.nodes,
+            SearchStats::percent(self.quiescence_nodes, self.nodes),
+            self.time.as_millis(),
+            self.nps()
+        )?;
+
+        match self.effective_branching_factor() {
+            Some(ebf) => writeln!(f, "effective branching factor: {:.2}", ebf)?,
+            None => writeln!(f, "effective branching factor: -")?,
+        }
+
+        writeln!(
+            f,
+            "first move cutoffs: {:.1}% of {}",
+            SearchStats::percent(self.first_move_cutoffs, self.cutoffs),
+            self.cutoffs
+        )?;
+
+        write!(
+            f,
+            "tt hits: {:.1}% of {} probes",
+            SearchStats::percent(self.tt_hits, self.tt_probes),
+            self.tt_probes
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_stats() {
+        let mut stats = SearchStats {
+            nodes: 3000,
+            quiescence_nodes: 1500,
+            cutoffs: 10,
+            first_move_cutoffs: 9,
+            iteration_nodes: vec![10, 100, 400],
+            time: Duration::from_millis(500),
+            ..SearchStats::default()
+        };
+
+        assert_eq!(stats.nps(), 6000);
+        assert_eq!(stats.effective_branching_factor(), Some(4.0));
+
+        stats.merge(&SearchStats {
+            nodes: 1000,
+            tt_probes: 4,
+            tt_hits: 1,
+            iteration_nodes: vec![1, 2],
+            ..SearchStats::default()
+        });
+
+        assert_eq!(stats.nodes, 4000);
+        assert_eq!(stats.iteration_nodes.len(), 3);
+
+        assert_eq!(
+            stats.to_string(),
+            "nodes: 4000, quiescence: 37.5%, time: 500 ms, nps: 8000\n\
+             effective branching factor: 4.00\n\
+             first move cutoffs: 90.0% of 10\n\
+             tt hits: 25.0% of 4 probes"
+        );
+
+        assert_eq!(SearchStats::default().effective_branching_factor(), None);
+    }
+}
@@ -0,0 +1,139 @@
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::Rng;
+
+/// full strength, no limits
+pub const MAX_SKILL: u32 = 20;
+
+/// Elo range mapped onto the skill levels for `UCI_Elo`
+pub const MIN_ELO: u32 = 800;
+pub const MAX_ELO: u32 = 2200;
+
+/// Playing strength, from 0 for beginners up to `MAX_SKILL`. The weaker levels search
+/// fewer plies and nodes, and pick a random move among the ones scoring close to the best.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Skill {
+    level: u32,
+}
+
+impl Default for Skill {
+    fn default() -> Self {
+        Skill::new(MAX_SKILL)
+    }
+}
+
+impl Skill {
+    pub fn new(level: u32) -> Skill {
+        Skill {
+            level: level.min(MAX_SKILL),
+        }
+    }
+
+    /// Skill level of a rating, linear over the supported Elo range.
+    pub fn from_elo(elo: u32) -> Skill {
+        let elo = elo.clamp(MIN_ELO, MAX_ELO);
+        let level = (elo - MIN_ELO) * MAX_SKILL / (MAX_ELO - MIN_ELO);
+
+        Skill::new(level)
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.level < MAX_SKILL
+    }
+
+    /// Deepest iteration allowed, from one ply at level 0 to five below full strength.
+    pub fn max_depth(&self) -> Option<u32> {
+        if self.is_limited() {
+            Some(1 + self.level / 4)
+        } else {
+            None
+        }
+    }
+
+    /// Nodes searched per move, doubling every second level.
+    pub fn node_limit(&self) -> Option<u64> {
+        if self.is_limited() {
+            Some(200 << (self.level / 2))
+        } else {
+            None
+        }
+    }
+
+    /// Largest random change of the move scores, in centipawns.
+    pub fn noise(&self) -> i32 {
+        (MAX_SKILL - self.level) as i32 * 15
+    }
+
+    /// Picks one of the root moves given their scores. The scores get random noise,
+    /// then the moves within the noise of the best one are chosen with weights
+    /// falling linearly with their distance to it.
+    pub fn pick_move(&self, scores: &[i32], rng: &mut StdRng) -> usize {
+        let noise = self.noise();
+
+        let noisy: Vec<i32> = scores
+            .iter()
+            .map(|score| if noise > 0 { score + rng.gen_range(-noise..=noise) } else { *score })
+            .collect();
+
+        let best = match noisy.iter().max() {
+            Some(best) => *best,
+            None => return 0,
+        };
+
+        let weights: Vec<i32> = noisy.iter().map(|score| (noise + 1 - (best - score)).max(0)).collect();
+
+        match WeightedIndex::new(&weights) {
+            Ok(distribution) => distribution.sample(rng),
+            Err(_) => noisy.iter().position(|score| *score == best).unwrap_or(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn test_skill_limits() {
+        assert_eq!(Skill::from_elo(0), Skill::new(0));
+        assert_eq!(Skill::from_elo(1500), Skill::new(10));
+        assert_eq!(Skill::from_elo(5000), Skill::default());
+
+        assert_eq!(Skill::new(0).max_depth(), Some(1));
+        assert_eq!(Skill::new(19).node_limit(), Some(200 << 9));
+        assert_eq!(Skill::default().max_depth(), None);
+        assert_eq!(Skill::default().node_limit(), None);
+        assert_eq!(Skill::default().noise(), 0);
+    }
+
+    #[test]
+    fn test_pick_move() {
+        let scores = [50, 40, -300, 45];
+
+        // full strength always takes the best move
+        let mut rng = StdRng::seed_from_u64(3);
+        assert!((0..100).all(|_| Skill::default().pick_move(&scores, &mut rng) == 0));
+
+        // weak levels vary among the close moves, and never take the blunder
+        let mut picked = [0; 4];
+        for _ in 0..1000 {
+            picked[Skill::new(15).pick_move(&scores, &mut rng)] += 1;
+        }
+
+        assert!(picked[0] > 0 && picked[1] > 0 && picked[3] > 0);
+        assert_eq!(picked[2], 0);
+
+        // the same seed gives the same choices
+        let choices = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..20).map(|_| Skill::new(0).pick_move(&scores, &mut rng)).collect::<Vec<usize>>()
+        };
+        assert_eq!(choices(11), choices(11));
+    }
+}
@@ -0,0 +1,333 @@
+use std::sync::OnceLock;
+
+use crate::bboard::BBoard;
+use crate::magic::{slider_attacks, Magic, BISHOP_DIRECTIONS, ROOK_DIRECTIONS};
+use crate::piece_moves::PieceMoveProvider;
+
+/// Attacks of the sliding pieces for a square and the board occupancy.
+/// The occupancy may include any pieces, also the ones outside the piece rays.
+pub trait SliderAttacks: Sync {
+    fn get_rook_attack_bits(&self, idx: usize, board: BBoard) -> BBoard;
+    fn get_bishop_attack_bits(&self, idx: usize, board: BBoard) -> BBoard;
+}
+
+/// Slider backend used by the move generator, selected by cargo feature.
+#[cfg(feature = "slider-pext")]
+pub type Sliders = Pext;
+
+#[cfg(all(feature = "slider-kogge-stone", not(feature = "slider-pext")))]
+pub type Sliders = KoggeStone;
+
+#[cfg(all(
+    feature = "slider-classical",
+    not(any(feature = "slider-pext", feature = "slider-kogge-stone"))
+))]
+pub type Sliders = Classical;
+
+#[cfg(not(any(feature = "slider-pext", feature = "slider-kogge-stone", feature = "slider-classical")))]
+pub type Sliders = Magic;
+
+/// Attack tables indexed by the occupancy bits extracted with the BMI2 `pext` instruction.
+/// Without BMI2 support the lookups fall back to the magic tables.
+pub struct Pext {
+    has_bmi2: bool,
+    rook: PextTable,
+    bishop: PextTable,
+}
+
+struct PextTable {
+    masks: [BBoard; 64],
+    offsets: [usize; 64],
+    attacks: Vec<BBoard>,
+}
+
+static PEXT: OnceLock<Pext> = OnceLock::new();
+
+impl PextTable {
+    fn new(masks: &[BBoard; 64], directions: &[(i32, i32); 4]) -> PextTable {
+        let mut result = PextTable {
+            masks: *masks,
+            offsets: [0; 64],
+            attacks: Vec::new(),
+        };
+
+        for (idx, mask) in masks.iter().enumerate() {
+            result.offsets[idx] = result.attacks.len();
+            result.attacks.resize(result.attacks.len() + (1 << mask.count_ones()), 0);
+
+            let mut field = 0u64;
+
+            loop {
+                let index = result.offsets[idx] + soft_pext(field, *mask) as usize;
+                result.attacks[index] = slider_attacks(idx, field, directions);
+
+                field = field.wrapping_sub(*mask) & mask;
+                if field == 0 {
+                    break;
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// Portable `pext`: gathers the bits of the value selected by the mask into the low bits.
+fn soft_pext(value: u64, mask: u64) -> u64 {
+    let mut result = 0u64;
+    let mut mask = mask;
+    let mut bit = 1u64;
+
+    while mask != 0 {
+        if value & mask & mask.wrapping_neg() != 0 {
+            result |= bit;
+        }
+
+        mask &= mask - 1;
+        bit <<= 1;
+    }
+
+    result
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi2")]
+unsafe fn hard_pext(value: u64, mask: u64) -> u64 {
+    std::arch::x86_64::_pext_u64(value, mask)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn detect_bmi2() -> bool {
+    is_x86_feature_detected!("bmi2")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn detect_bmi2() -> bool {
+    false
+}
+
+impl Pext {
+    /// Process-wide tables, built on the first call and shared by all threads.
+    pub fn shared() -> &'static Pext {
+        PEXT.get_or_init(|| {
+            let provider = PieceMoveProvider::shared();
+
+            Pext {
+                has_bmi2: detect_bmi2(),
+                rook: PextTable::new(&provider.inner_rook_attack_bits, &ROOK_DIRECTIONS),
+                bishop: PextTable::new(&provider.inner_bishop_attack_bits, &BISHOP_DIRECTIONS),
+            }
+        })
+    }
+
+    #[inline]
+    fn lookup(&self, table: &PextTable, idx: usize, board: BBoard) -> Option<BBoard> {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if self.has_bmi2 {
+                // SAFETY: the bmi2 support is checked at runtime when the tables are built
+                let index = unsafe { hard_pext(board, table.masks[idx]) } as usize;
+                return Some(table.attacks[table.offsets[idx] + index]);
+            }
+        }
+
+        let _ = (table, idx, board);
+        None
+    }
+}
+
+impl SliderAttacks for Pext {
+    #[inline]
+    fn get_rook_attack_bits(&self, idx: usize, board: BBoard) -> BBoard {
+        self.lookup(&self.rook, idx, board)
+            .unwrap_or_else(|| Magic::shared().get_rook_attack_bits(idx, board))
+    }
+
+    #[inline]
+    fn get_bishop_attack_bits(&self, idx: usize, board: BBoard) -> BBoard {
+        self.lookup(&self.bishop, idx, board)
+            .unwrap_or_else(|| Magic::shared().get_bishop_attack_bits(idx, board))
+    }
+}
+
+/// Table-free attacks with Kogge-Stone parallel prefix fills.
+pub struct KoggeStone;
+
+static KOGGE_STONE: KoggeStone = KoggeStone;
+
+const NOT_A_FILE: BBoard = 0xfefe_fefe_fefe_fefe;
+const NOT_H_FILE: BBoard = 0x7f7f_7f7f_7f7f_7f7f;
+
+#[inline]
+fn shift(board: BBoard, amount: i32) -> BBoard {
+    if amount > 0 {
+        board << amount
+    } else {
+        board >> -amount
+    }
+}
+
+/// Attacks of the slider bits in one direction, given by the shift amount and the wrap mask.
+#[inline]
+fn occluded_fill(slider: BBoard, empty: BBoard, amount: i32, wrap_mask: BBoard) -> BBoard {
+    let mut slider = slider;
+    let mut empty = empty & wrap_mask;
+
+    slider |= empty & shift(slider, amount);
+    empty &= shift(empty, amount);
+    slider |= empty & shift(slider, amount * 2);
+    empty &= shift(empty, amount * 2);
+    slider |= empty & shift(slider, amount * 4);
+
+    shift(slider, amount) & wrap_mask
+}
+
+impl KoggeStone {
+    pub fn shared() -> &'static KoggeStone {
+        &KOGGE_STONE
+    }
+}
+
+impl SliderAttacks for KoggeStone {
+    #[inline]
+    fn get_rook_attack_bits(&self, idx: usize, board: BBoard) -> BBoard {
+        let slider = 1u64 << idx;
+        let empty = !board;
+
+        occluded_fill(slider, empty, 8, !0)
+            | occluded_fill(slider, empty, -8, !0)
+            | occluded_fill(slider, empty, 1, NOT_A_FILE)
+            | occluded_fill(slider, empty, -1, NOT_H_FILE)
+    }
+
+    #[inline]
+    fn get_bishop_attack_bits(&self, idx: usize, board: BBoard) -> BBoard {
+        let slider = 1u64 << idx;
+        let empty = !board;
+
+        occluded_fill(slider, empty, 9, NOT_A_FILE)
+            | occluded_fill(slider, empty, 7, NOT_H_FILE)
+            | occluded_fill(slider, empty, -7, NOT_A_FILE)
+            | occluded_fill(slider, empty, -9, NOT_H_FILE)
+    }
+}
+
+/// Classical ray attacks: each ray is cut behind its first blocker.
+pub struct Classical;
+
+static CLASSICAL: Classical = Classical;
+
+/// rays in the directions of `ROOK_DIRECTIONS` followed by `BISHOP_DIRECTIONS`
+static RAYS: [[BBoard; 64]; 8] = generate_rays();
+
+const fn generate_rays() -> [[BBoard; 64]; 8] {
+    let mut result = [[0u64; 64]; 8];
+
+    let mut dir = 0;
+    while dir < 8 {
+        let direction = if dir < 4 { ROOK_DIRECTIONS[dir] } else { BISHOP_DIRECTIONS[dir - 4] };
+
+        let mut idx = 0;
+        while idx < 64 {
+            // walking the same direction four times gives the single ray
+            let single = [direction, direction, direction, direction];
+            result[dir][idx] = slider_attacks(idx, 0, &single);
+            idx += 1;
+        }
+
+        dir += 1;
+    }
+
+    result
+}
+
+impl Classical {
+    pub fn shared() -> &'static Classical {
+        &CLASSICAL
+    }
+
+    #[inline]
+    fn ray_attacks(&self, dir: usize, idx: usize, board: BBoard) -> BBoard {
+        let ray = RAYS[dir][idx];
+        let blockers = ray & board;
+
+        if blockers == 0 {
+            return ray;
+        }
+
+        // the rays towards higher squares are blocked by the lowest bit, the others by the highest
+        let blocker = if ray > 1u64 << idx {
+            blockers.trailing_zeros()
+        } else {
+            63 - blockers.leading_zeros()
+        };
+
+        ray ^ RAYS[dir][blocker as usize]
+    }
+}
+
+impl SliderAttacks for Classical {
+    #[inline]
+    fn get_rook_attack_bits(&self, idx: usize, board: BBoard) -> BBoard {
+        (0..4).fold(0, |attacks, dir| attacks | self.ray_attacks(dir, idx, board))
+    }
+
+    #[inline]
+    fn get_bishop_attack_bits(&self, idx: usize, board: BBoard) -> BBoard {
+        (4..8).fold(0, |attacks, dir| attacks | self.ray_attacks(dir, idx, board))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_backends_agree() {
+        let pext = Pext::shared();
+        let pext_fallback = Pext {
+            has_bmi2: false,
+            rook: PextTable::new(&[0; 64], &ROOK_DIRECTIONS),
+            bishop: PextTable::new(&[0; 64], &BISHOP_DIRECTIONS),
+        };
+
+        let backends: [&dyn SliderAttacks; 5] = [
+            Magic::shared(),
+            pext,
+            &pext_fallback,
+            KoggeStone::shared(),
+            Classical::shared(),
+        ];
+
+        let mut rng = StdRng::seed_from_u64(7);
+
+        for idx in 0..64 {
+            for sample in 0..200 {
+                // sparse and dense boards
+                let board = match sample % 3 {
+                    0 => rng.gen::<u64>(),
+                    1 => rng.gen::<u64>() & rng.gen::<u64>(),
+                    _ => rng.gen::<u64>() & rng.gen::<u64>() & rng.gen::<u64>(),
+                };
+
+                let rook = slider_attacks(idx, board, &ROOK_DIRECTIONS);
+                let bishop = slider_attacks(idx, board, &BISHOP_DIRECTIONS);
+
+                for (backend_idx, backend) in backends.iter().enumerate() {
+                    assert_eq!(backend.get_rook_attack_bits(idx, board), rook, "{} {} {:#x}", backend_idx, idx, board);
+                    assert_eq!(backend.get_bishop_attack_bits(idx, board), bishop, "{} {} {:#x}", backend_idx, idx, board);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_soft_pext() {
+        assert_eq!(soft_pext(0b1011_0110, 0b1111_0000), 0b1011);
+        assert_eq!(soft_pext(0b1011_0110, 0b0101_0101), 0b0110);
+        assert_eq!(soft_pext(u64::MAX, 0x8000_0000_0000_0001), 0b11);
+    }
+}
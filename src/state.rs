@@ -4,6 +4,7 @@ use std::fmt::Formatter;
 use crate::bboard::*;
 use crate::debug::Demo;
 use crate::game_setup::ChessMove;
+use crate::zobrist;
 
 pub enum CastleSide {
     Queen,
@@ -100,6 +101,12 @@ impl BBPiece {
         (*self) as usize
     }
 
+    /// True for the boards of actual pieces, false for the helper boards
+    #[inline]
+    pub fn is_piece(&self) -> bool {
+        self.idx() % BBPIECE_MIDDLE < BBPiece::WAll.idx()
+    }
+
     #[inline]
     pub fn opposite_idx(&self) -> usize {
         let ret = (*self) as usize;
@@ -122,8 +129,10 @@ impl BBPiece {
             330,   // Bishop
             900,   // Queen
             0,     // all
-            0,     // WAttack
-            0,     // WCastles
+            0,     // WPins
+            0,     // WAttacks
+            0,     // WKCastle
+            0,     // WQCastle
             0,     // WEnPassant
             -40000, // king
             -100,   // pawn
@@ -132,8 +141,10 @@ impl BBPiece {
             -330,   // bishop
             -900,   // queen
             -0,     // all
-            -0,     // BAttack
-            -0,     // BCastles
+            -0,     // BPins
+            -0,     // BAttacks
+            -0,     // BKCastle
+            -0,     // BQCastle
             -0,     // BEnPassant
         ];
 
@@ -158,36 +169,6 @@ impl BBPiece {
         &BBPIECE_ARRAY
     }
 
-    #[inline]
-    pub fn to_string(&self) -> String {
-        match self {
-            BBPiece::WKing => "King".to_string(),
-            BBPiece::WPawn => "Pawn".to_string(),
-            BBPiece::WRook => "Rook".to_string(),
-            BBPiece::WKnight => "Knight".to_string(),
-            BBPiece::WBishop  => "Bishop".to_string(),
-            BBPiece::WQueen  => "Queen".to_string(),
-            BBPiece::WAll  => "All".to_string(),
-            BBPiece::WPins  => "Pins".to_string(),
-            BBPiece::WAttacks  => "Attacks".to_string(),
-            BBPiece::WKCastle  => "KCastle".to_string(),
-            BBPiece::WQCastle  => "QCastle".to_string(),
-            BBPiece::WEnPassant  => "EnPassant".to_string(),
-            BBPiece::BKing => "king".to_string(),
-            BBPiece::BPawn => "pawn".to_string(),
-            BBPiece::BRook => "rook".to_string(),
-            BBPiece::BKnight => "knigt".to_string(),
-            BBPiece::BBishop  => "bishop".to_string(),
-            BBPiece::BQueen  => "queen".to_string(),
-            BBPiece::BAll  => "all".to_string(),
-            BBPiece::BPins  => "pins".to_string(),
-            BBPiece::BAttacks  => "attacks".to_string(),
-            BBPiece::BKCastle  => "kCastle".to_string(),
-            BBPiece::BQCastle  => "qCastle".to_string(),
-            BBPiece::BEnPassant  => "enPassant".to_string(),
-        }
-    }
-
     #[inline]
     pub fn to_char(&self) -> char {
         match *self {
@@ -308,6 +289,38 @@ impl fmt::Display for Side {
     }
 }
 
+impl fmt::Display for BBPiece {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BBPiece::WKing => "King",
+            BBPiece::WPawn => "Pawn",
+            BBPiece::WRook => "Rook",
+            BBPiece::WKnight => "Knight",
+            BBPiece::WBishop  => "Bishop",
+            BBPiece::WQueen  => "Queen",
+            BBPiece::WAll  => "All",
+            BBPiece::WPins  => "Pins",
+            BBPiece::WAttacks  => "Attacks",
+            BBPiece::WKCastle  => "KCastle",
+            BBPiece::WQCastle  => "QCastle",
+            BBPiece::WEnPassant  => "EnPassant",
+            BBPiece::BKing => "king",
+            BBPiece::BPawn => "pawn",
+            BBPiece::BRook => "rook",
+            BBPiece::BKnight => "knigt",
+            BBPiece::BBishop  => "bishop",
+            BBPiece::BQueen  => "queen",
+            BBPiece::BAll  => "all",
+            BBPiece::BPins  => "pins",
+            BBPiece::BAttacks  => "attacks",
+            BBPiece::BKCastle  => "kCastle",
+            BBPiece::BQCastle  => "qCastle",
+            BBPiece::BEnPassant  => "enPassant",
+        };
+
+        write!(f, "{}", name)
+    }
+}
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct ChessState {
@@ -315,8 +328,13 @@ pub struct ChessState {
 
     bboards: [BBoard; BBPIECE_COUNT],
 
+    /// piece on each square, kept in sync with the piece bit boards
+    pieces: [Option<BBPiece>; 64],
+
     pub half_move_count: u32,
     pub full_move_count: u32,
+
+    hash_key: u64,
 }
 
 impl ChessState {
@@ -324,58 +342,75 @@ impl ChessState {
         ChessState {
             next_to_move: Side::White,
             bboards: [0u64; BBPIECE_COUNT],
+            pieces: [None; 64],
             half_move_count: 0,
             full_move_count: 0,
+            hash_key: 0,
         }
     }
 
+    /// Zobrist hash of the position, kept up to date by `do_move` and `undo_move`.
     #[inline]
-    pub fn piece_at(&self, move_to: BBoard) -> BBPiece {
+    pub fn hash_key(&self) -> u64 {
+        self.hash_key
+    }
 
-        if move_to & self.bboard(BBPiece::WAll) > 0 {
-            if self.bboards[BBPiece::WPawn as usize] & move_to > 0 {
-                return BBPiece::WPawn;
-            }
-            if self.bboards[BBPiece::WRook as usize] & move_to > 0 {
-                return BBPiece::WRook;
-            }
-            if self.bboards[BBPiece::WKnight as usize] & move_to > 0 {
-                return BBPiece::WKnight;
-            }
-            if self.bboards[BBPiece::WBishop as usize] & move_to > 0 {
-                return BBPiece::WBishop;
-            }
-            if self.bboards[BBPiece::WQueen as usize] & move_to > 0 {
-                return BBPiece::WQueen;
-            }
-            if self.bboards[BBPiece::WKing as usize] & move_to > 0 {
-                return BBPiece::WKing;
-            }
+    /// Computes the Zobrist hash from scratch. Needed after changing
+    /// the boards directly through `bboard_mut`.
+    pub fn compute_hash_key(&self) -> u64 {
+        let mut result = 0u64;
+
+        for piece in BBPiece::get_all() {
+            result ^= zobrist::delta_key(*piece, self.bboard(*piece));
         }
 
-        if move_to & self.bboard(BBPiece::BAll) > 0 {
-            if self.bboards[BBPiece::BPawn as usize] & move_to > 0 {
-                return BBPiece::BPawn;
-            }
-            if self.bboards[BBPiece::BRook as usize] & move_to > 0 {
-                return BBPiece::BRook;
-            }
-            if self.bboards[BBPiece::BKnight as usize] & move_to > 0 {
-                return BBPiece::BKnight;
-            }
-            if self.bboards[BBPiece::BBishop as usize] & move_to > 0 {
-                return BBPiece::BBishop;
-            }
-            if self.bboards[BBPiece::BQueen as usize] & move_to > 0 {
-                return BBPiece::BQueen;
-            }
-            if self.bboards[BBPiece::BKing as usize] & move_to > 0 {
-                return BBPiece::BKing;
+        if self.next_to_move == Side::Black {
+            result ^= zobrist::SIDE_KEY;
+        }
+
+        result
+    }
+
+    #[inline]
+    pub fn update_hash_key(&mut self) {
+        self.hash_key = self.compute_hash_key();
+    }
+
+    /// Rebuilds the piece on square table from the bit boards. Needed after
+    /// changing the boards directly through `bboard_mut`.
+    pub fn update_pieces(&mut self) {
+        self.pieces = [None; 64];
+
+        for piece in BBPiece::get_pieces() {
+            for square in Bitboard(self.bboard(*piece)) {
+                self.pieces[square.idx()] = Some(*piece);
             }
         }
+    }
+
+    /// Piece standing on the given square, if any.
+    #[inline]
+    pub fn piece_on(&self, sq: Square) -> Option<BBPiece> {
+        self.pieces[sq.idx()]
+    }
+
+    /// Updates the piece on square table after the delta has been applied to its board.
+    #[inline]
+    fn update_pieces_delta(&mut self, piece: BBPiece, delta: BBoard) {
+        if !piece.is_piece() {
+            return;
+        }
+
+        let board = Bitboard(self.bboard(piece));
 
-        self.demo();
-        panic!("Piece not found at the given position!");
+        for square in Bitboard(delta) {
+            if board.contains(square) {
+                self.pieces[square.idx()] = Some(piece);
+            } else if self.pieces[square.idx()] == Some(piece) {
+                // a capturing piece may have been placed here already
+                self.pieces[square.idx()] = None;
+            }
+        }
     }
 
     #[inline]
@@ -389,7 +424,7 @@ impl ChessState {
     }
 
     #[inline]
-    pub fn bboard_ofs(&mut self, board: BBPiece, offset: usize) -> BBoard {
+    pub fn bboard_ofs(&self, board: BBPiece, offset: usize) -> BBoard {
         debug_assert!(board.get_side() == Side::White);
         self.bboards[board.idx() + offset]
     }
@@ -401,8 +436,11 @@ impl ChessState {
     pub fn do_move(&mut self, chess_move: &ChessMove) {
         for delta in chess_move.deltas.iter() {
             *self.bboard_mut(delta.0) ^= delta.1;
+            self.hash_key ^= zobrist::delta_key(delta.0, delta.1);
+            self.update_pieces_delta(delta.0, delta.1);
         }
 
+        self.hash_key ^= zobrist::SIDE_KEY;
         self.next_to_move = self.next_to_move.opposite();
 
         if self.next_to_move == Side::White {
@@ -415,8 +453,12 @@ impl ChessState {
     pub fn undo_move(&mut self, chess_move: &ChessMove) {
         for delta in chess_move.deltas.iter() {
             *self.bboard_mut(delta.0) ^= delta.1;
+            self.hash_key ^= zobrist::delta_key(delta.0, delta.1);
+            self.update_pieces_delta(delta.0, delta.1);
         }
 
+        self.hash_key ^= zobrist::SIDE_KEY;
+
         if self.next_to_move == Side::White {
             self.full_move_count -= 1;
         }
@@ -425,14 +467,37 @@ impl ChessState {
         self.next_to_move = self.next_to_move.opposite();
     }
 
+    /// Passes the turn to the opponent: flips the side to move and clears the en-passant squares.
+    /// Returns the move to pass to `undo_null_move`.
+    pub fn do_null_move(&mut self) -> ChessMove {
+        let mut null_move = ChessMove::new(self.next_to_move, 0, 0, None);
+
+        for piece in [BBPiece::WEnPassant, BBPiece::BEnPassant].iter() {
+            let en_passant = self.bboard(*piece);
+
+            if en_passant > 0 {
+                null_move.deltas.push((*piece, en_passant));
+            }
+        }
+
+        self.do_move(&null_move);
+
+        null_move
+    }
+
+    /// Takes back a move made with `do_null_move`, restoring the en-passant squares.
+    pub fn undo_null_move(&mut self, null_move: &ChessMove) {
+        debug_assert!(null_move.move_from == 0 && null_move.move_to == 0);
+
+        self.undo_move(null_move);
+    }
+
     pub fn castle_state(&self, side: Side) -> (bool, bool) {
-        let bcastles = if side == Side::White {
+        if side == Side::White {
             (self.bboard(BBPiece::WKCastle) > 0, self.bboard(BBPiece::WQCastle) > 0)
         } else {
             (self.bboard(BBPiece::BKCastle) > 0, self.bboard(BBPiece::BQCastle) > 0)
-        };
-
-        bcastles
+        }
     }
 
 
@@ -451,8 +516,8 @@ impl ChessState {
             } else {
                 0u64
             };
-            
-            return (king_side_castle, queen_side_castle);
+
+            (king_side_castle, queen_side_castle)
         } else {
             let king_side_castle = if kcastle {
                 BKING_SIDE_CASLTE
@@ -466,9 +531,8 @@ impl ChessState {
                 0u64
             };
 
-            return (king_side_castle, queen_side_castle);   
+            (king_side_castle, queen_side_castle)
         }
-        
     }
 
     #[inline]
@@ -478,6 +542,8 @@ impl ChessState {
         } else {
             *self.bboard_mut(BBPiece::BEnPassant) = en_passant;
         }
+
+        self.update_hash_key();
     }
 
     #[inline]
@@ -490,6 +556,8 @@ impl ChessState {
             *self.bboard_mut(BBPiece::BKCastle) = if state.0 {BKING_SIDE_CASLTE} else {0u64};
             *self.bboard_mut(BBPiece::BQCastle) = if state.1 {BQUEEN_SIDE_CASLTE} else {0u64};
         };
+
+        self.update_hash_key();
     }
 
     #[inline]
@@ -500,6 +568,8 @@ impl ChessState {
         } else {
             *self.bboard_mut(BBPiece::BKCastle) = if value {BKING_SIDE_CASLTE} else {0u64};
         };
+
+        self.update_hash_key();
     }
 
     pub fn set_queen_side_castle(&mut self, side: Side, value: bool) {
@@ -509,6 +579,8 @@ impl ChessState {
         } else {
             *self.bboard_mut(BBPiece::BQCastle) = if value {BQUEEN_SIDE_CASLTE} else {0u64};
         };
+
+        self.update_hash_key();
     }
 
     pub fn to_fen(&self) -> String {
@@ -518,9 +590,9 @@ impl ChessState {
             let bit_board = self.bboards[piece.idx()];
             let c = piece.to_char();
 
-            for i in 0..64 {
+            for (i, square) in board.iter_mut().enumerate() {
                 if (bit_board >> i) & 1 == 1 {
-                    board[i] = c;
+                    *square = c;
                 }
             }
         }
@@ -598,9 +670,9 @@ impl ChessState {
     pub fn from_fen(fen: &str) -> ChessState {
         let mut state = ChessState::new_empty();
 
-        let split: Vec<&str> = fen.trim().split(" ").collect();
+        let split: Vec<&str> = fen.trim().split(' ').collect();
 
-        if let Some(board) = split.get(0) {
+        if let Some(board) = split.first() {
             // parse board pieces
             let mut idx = 0u32;
             for c in board.as_bytes() {
@@ -634,7 +706,7 @@ impl ChessState {
                     panic!("unrecognized FEN board input: {}", board);
                 }
 
-                idx = idx + 1;
+                idx += 1;
             }
         }
 
@@ -682,6 +754,9 @@ impl ChessState {
             state.full_move_count = moves.parse().unwrap();
         }
 
+        state.update_hash_key();
+        state.update_pieces();
+
         state
     }
 
@@ -696,15 +771,15 @@ impl ChessState {
             let board = self.bboard(*piece);
             let c = piece.to_char();
 
-            for i in 0..64 {
+            for (i, square) in result.iter_mut().enumerate() {
                 if (board >> i) & 1 == 1 {
-                    if result[i] != '.' {
+                    if *square != '.' {
                         println!(
                             "board conflict between {} and {} at index {}",
-                            result[i], c, i
+                            square, c, i
                         );
                     }
-                    result[i] = c;
+                    *square = c;
                 }
             }
         }
@@ -736,7 +811,7 @@ impl Demo for ChessState {
         self.print();
 
         for piece in BBPiece::get_all() {
-            println!("{}",piece.to_string());
+            println!("{}",piece);
             bb_print(self.bboard(*piece));
         }
 
@@ -774,6 +849,113 @@ mod tests {
         assert_ne!(state1, state7);
     }
 
+    #[test]
+    fn test_hash_key() {
+        use crate::move_generator::MoveGenerator;
+
+        let generator = MoveGenerator::new();
+        let mut state = ChessState::new_game();
+        let initial_key = state.hash_key();
+
+        assert_eq!(initial_key, state.compute_hash_key());
+        assert_ne!(initial_key, ChessState::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1").hash_key());
+        assert_ne!(initial_key, ChessState::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w Kkq - 0 1").hash_key());
+
+        // the same position reached by different move orders
+        let play = |state: &mut ChessState, moves: &[&str]| {
+            for m in moves.iter() {
+                let parsed = ChessMove::parse(m, state).unwrap();
+                let legal = generator.legal_move(state, &parsed).unwrap();
+                state.do_move(&legal);
+                assert_eq!(state.hash_key(), state.compute_hash_key());
+            }
+        };
+
+        let mut state1 = ChessState::new_game();
+        play(&mut state1, &["g1f3", "g8f6", "b1c3", "b8c6"]);
+
+        let mut state2 = ChessState::new_game();
+        play(&mut state2, &["b1c3", "b8c6", "g1f3", "g8f6"]);
+
+        assert_eq!(state1.hash_key(), state2.hash_key());
+
+        // en-passant square is part of the key
+        play(&mut state1, &["e2e4"]);
+        play(&mut state2, &["e2e3", "a8b8", "e3e4", "b8a8"]);
+        assert_ne!(state1.hash_key(), state2.hash_key());
+
+        let mut moves: Vec<ChessMove> = Vec::new();
+        generator.generate_moves(&mut state, &mut moves);
+
+        for m in moves.iter() {
+            state.do_move(m);
+            assert_eq!(state.hash_key(), state.compute_hash_key());
+            state.undo_move(m);
+        }
+
+        assert_eq!(state.hash_key(), initial_key);
+    }
+
+    #[test]
+    fn test_null_move() {
+        let mut state = ChessState::from_fen("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3");
+        let initial = state.clone();
+
+        let null_move = state.do_null_move();
+
+        assert_eq!(state.next_to_move, Side::White);
+        assert_eq!(state.en_passant_string(), "-");
+        assert_eq!(state.hash_key(), state.compute_hash_key());
+        assert_eq!(state.hash_key(), ChessState::from_fen("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 4").hash_key());
+
+        state.undo_null_move(&null_move);
+
+        assert_eq!(state, initial);
+    }
+
+    #[test]
+    fn test_piece_on() {
+        use crate::move_generator::MoveGenerator;
+
+        fn check_pieces(generator: &MoveGenerator, depth: u32, state: &mut ChessState) {
+            let mut rebuilt = state.clone();
+            rebuilt.update_pieces();
+            assert_eq!(state.pieces, rebuilt.pieces, "{}", state.to_fen());
+
+            if depth == 0 {
+                return;
+            }
+
+            let mut moves: Vec<ChessMove> = Vec::new();
+            generator.generate_moves(state, &mut moves);
+
+            for m in moves.iter() {
+                state.do_move(m);
+                check_pieces(generator, depth - 1, state);
+                state.undo_move(m);
+            }
+        }
+
+        let state = ChessState::new_game();
+        assert_eq!(state.piece_on(Square::parse("e1").unwrap()), Some(BBPiece::WKing));
+        assert_eq!(state.piece_on(Square::parse("d8").unwrap()), Some(BBPiece::BQueen));
+        assert_eq!(state.piece_on(Square::parse("d4").unwrap()), None);
+
+        let generator = MoveGenerator::new();
+
+        // castles, en-passant and promotions with captures
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - ",
+        ]
+        .iter()
+        {
+            let mut state = ChessState::from_fen(fen);
+            check_pieces(&generator, 3, &mut state);
+        }
+    }
+
     #[test]
     fn test_mut_board() {
         let mut state = ChessState::from_fen("8/8/8/8/8/8/8/8 w - -");
@@ -0,0 +1,258 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::engine::{is_mate_score, MATE_SCORE};
+use crate::game_setup::ChessMove;
+use crate::state::{BBPiece, Side};
+
+/// Kind of the stored score relative to the search window.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Bound {
+    Exact,
+    /// the search failed high, the score is at least this
+    Lower,
+    /// the search failed low, the score is at most this
+    Upper,
+}
+
+impl Bound {
+    fn from_bits(bits: u64) -> Bound {
+        match bits {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        }
+    }
+
+    fn bits(self) -> u64 {
+        match self {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        }
+    }
+}
+
+/// Search result of a position, as read from the table.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct TTEntry {
+    pub depth: u32,
+    /// score with the mate distance counted from the stored position
+    pub score: i32,
+    pub bound: Bound,
+    /// best move as from and to square and promotion piece without the side offset
+    best_move: Option<(u8, u8, Option<u8>)>,
+}
+
+impl TTEntry {
+    /// Score relative to the root, for the position found at the given ply.
+    pub fn score(&self, ply: usize) -> i32 {
+        score_from_tt(self.score, ply)
+    }
+
+    /// Best move without deltas, to be checked with `legal_move` before use.
+    pub fn best_move(&self, side: Side) -> Option<ChessMove> {
+        self.best_move.map(|(from, to, promote)| {
+            let promote = promote.map(|p| BBPiece::from_usize(p as usize + side.offset()));
+            ChessMove::new(side, 1u64 << from, 1u64 << to, promote)
+        })
+    }
+
+    // packed as: score 32 bits, depth 8, bound 2, move flag 1, from 6, to 6, promotion flag 1, promotion 4
+    fn pack(&self) -> u64 {
+        let mut data = (self.score as u32 as u64) | (packed_depth(self.depth) << 32) | (self.bound.bits() << 40);
+
+        if let Some((from, to, promote)) = self.best_move {
+            data |= 1 << 42 | (from as u64) << 43 | (to as u64) << 49;
+
+            if let Some(promote) = promote {
+                data |= 1 << 55 | (promote as u64) << 56;
+            }
+        }
+
+        data
+    }
+
+    fn unpack(data: u64) -> TTEntry {
+        let best_move = if data & 1 << 42 > 0 {
+            let promote = if data & 1 << 55 > 0 { Some((data >> 56 & 0xf) as u8) } else { None };
+            Some(((data >> 43 & 0x3f) as u8, (data >> 49 & 0x3f) as u8, promote))
+        } else {
+            None
+        };
+
+        TTEntry {
+            depth: (data >> 32 & 0xff) as u32,
+            score: data as u32 as i32,
+            bound: Bound::from_bits(data >> 40 & 0x3),
+            best_move,
+        }
+    }
+}
+
+#[inline]
+fn packed_depth(depth: u32) -> u64 {
+    depth.min(0xff) as u64
+}
+
+/// Mate scores are stored relative to the position, so they stay valid when it is reached at another ply.
+#[inline]
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if !is_mate_score(score) {
+        score
+    } else if score > 0 {
+        score + ply as i32
+    } else {
+        score - ply as i32
+    }
+}
+
+#[inline]
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if !is_mate_score(score) {
+        score
+    } else if score > 0 {
+        (score - ply as i32).min(MATE_SCORE)
+    } else {
+        (score + ply as i32).max(-MATE_SCORE)
+    }
+}
+
+struct Slot {
+    /// hash key xor-ed with the data, so a torn write by two threads does not verify
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+/// Transposition table shared by the search threads without locking.
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    mask: usize,
+}
+
+impl TranspositionTable {
+    /// Table of the largest power of two entries fitting in the given size.
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let bytes = size_mb.max(1) * 1024 * 1024;
+        let fitting = (bytes / std::mem::size_of::<Slot>()).max(1);
+        let count = 1 << (usize::BITS - 1 - fitting.leading_zeros());
+
+        TranspositionTable {
+            slots: (0..count)
+                .map(|_| Slot {
+                    check: AtomicU64::new(0),
+                    data: AtomicU64::new(0),
+                })
+                .collect(),
+            mask: count - 1,
+        }
+    }
+
+    pub fn clear(&self) {
+        for slot in self.slots.iter() {
+            slot.check.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    #[inline]
+    fn slot(&self, key: u64) -> &Slot {
+        &self.slots[key as usize & self.mask]
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TTEntry> {
+        let slot = self.slot(key);
+
+        let data = slot.data.load(Ordering::Relaxed);
+        let check = slot.check.load(Ordering::Relaxed);
+
+        // zero data is an empty slot, a real entry has a nonzero depth
+        if check ^ data != key || data == 0 {
+            return None;
+        }
+
+        Some(TTEntry::unpack(data))
+    }
+
+    /// Stores the search result of the position at the given ply. A deeper result
+    /// of the same position is kept, other positions are always replaced.
+    pub fn store(&self, key: u64, ply: usize, depth: u32, score: i32, bound: Bound, best_move: Option<&ChessMove>) {
+        let slot = self.slot(key);
+
+        if let Some(old) = self.probe(key) {
+            if old.depth > depth && bound != Bound::Exact {
+                return;
+            }
+        }
+
+        let best_move = best_move.map(|m| {
+            let promote = m.promote.map(|p| (p.idx() - p.get_side().offset()) as u8);
+            (m.move_from.trailing_zeros() as u8, m.move_to.trailing_zeros() as u8, promote)
+        });
+
+        let entry = TTEntry {
+            depth,
+            score: score_to_tt(score, ply),
+            bound,
+            best_move,
+        };
+
+        let data = entry.pack();
+
+        slot.data.store(data, Ordering::Relaxed);
+        slot.check.store(key ^ data, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::ChessState;
+
+    #[test]
+    fn test_table_size() {
+        let tt = TranspositionTable::new(16);
+        assert_eq!(tt.slots.len() * std::mem::size_of::<Slot>(), 16 * 1024 * 1024);
+
+        // rounded down to a power of two
+        let tt = TranspositionTable::new(3);
+        assert_eq!(tt.slots.len() * std::mem::size_of::<Slot>(), 2 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        let state = ChessState::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1");
+        let promotion = ChessMove::parse("b7b8n", &state).unwrap();
+
+        tt.store(0x1234_5678_9abc_def0, 3, 5, -250, Bound::Upper, Some(&promotion));
+
+        let entry = tt.probe(0x1234_5678_9abc_def0).unwrap();
+        assert_eq!((entry.depth, entry.score(3), entry.bound), (5, -250, Bound::Upper));
+        assert!(entry.best_move(Side::White).unwrap().is_same(&promotion));
+
+        // the same slot with another key
+        assert_eq!(tt.probe(0x1234_5678_9abc_def0 ^ 1 << 63), None);
+
+        // the deeper result stays
+        tt.store(0x1234_5678_9abc_def0, 3, 2, 100, Bound::Lower, None);
+        assert_eq!(tt.probe(0x1234_5678_9abc_def0).unwrap().depth, 5);
+
+        tt.clear();
+        assert_eq!(tt.probe(0x1234_5678_9abc_def0), None);
+    }
+
+    #[test]
+    fn test_mate_scores() {
+        let tt = TranspositionTable::new(1);
+
+        // mate in 3 plies from a position at ply 4 is mate in 7 plies from the root
+        tt.store(42, 4, 3, MATE_SCORE - 7, Bound::Exact, None);
+
+        let entry = tt.probe(42).unwrap();
+        assert_eq!(entry.score, MATE_SCORE - 3);
+        assert_eq!(entry.score(2), MATE_SCORE - 5);
+
+        tt.store(43, 4, 3, -MATE_SCORE + 6, Bound::Exact, None);
+        assert_eq!(tt.probe(43).unwrap().score(1), -MATE_SCORE + 3);
+    }
+}
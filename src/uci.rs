@@ -0,0 +1,301 @@
+use crate::engine::{is_mate_score, Iteration, INFINITE_DEPTH, MATE_SCORE};
+use crate::game_setup::{ChessMove, GameSetup};
+use crate::messaging::{get_message, send_message};
+use crate::options;
+use crate::options::OPTIONS;
+use crate::ponder::{search_in_background, InputAction};
+use crate::state::ChessState;
+
+/// Applies a `position` command: `startpos` or `fen <FEN>`, optionally followed by `moves ...`.
+pub fn set_position(setup: &mut GameSetup, argument: &str) -> Result<(), String> {
+    let (position, moves) = match argument.find("moves") {
+        Some(idx) => (argument[..idx].trim(), argument[idx + "moves".len()..].trim()),
+        None => (argument.trim(), ""),
+    };
+
+    let state = if position == "startpos" {
+        ChessState::new_game()
+    } else if let Some(fen) = position.strip_prefix("fen") {
+        ChessState::from_fen(fen.trim())
+    } else {
+        return Err(format!("unknown position: {}", position));
+    };
+
+    setup.game_state = state;
+    setup.history.clear();
+
+    for move_str in moves.split_whitespace() {
+        let parsed = ChessMove::parse(move_str, &setup.game_state)?;
+        let legal = setup
+            .engine
+            .legal_move(&mut setup.game_state, &parsed)
+            .ok_or_else(|| format!("illegal move {}", move_str))?;
+
+        setup.play_move(legal);
+    }
+
+    Ok(())
+}
+
+/// Applies a `setoption name <NAME> value <VALUE>` command.
+pub fn set_option(setup: &mut GameSetup, argument: &str) -> Result<(), String> {
+    let argument = argument.trim().strip_prefix("name").ok_or("missing option name")?;
+
+    let (name, value) = match argument.find(" value ") {
+        Some(idx) => (argument[..idx].trim(), argument[idx + " value ".len()..].trim()),
+        None => (argument.trim(), ""),
+    };
+
+    options::set_option(setup, name, value)
+}
+
+/// Score of the `info` output, in centipawns or in moves to mate, negative when getting mated.
+pub fn score_string(score: i32) -> String {
+    if !is_mate_score(score) {
+        format!("cp {}", score)
+    } else if score > 0 {
+        format!("mate {}", (MATE_SCORE - score + 1) / 2)
+    } else {
+        format!("mate {}", -(MATE_SCORE + score) / 2)
+    }
+}
+
+/// The `info` line of a completed iteration.
+pub fn info_message(iteration: &Iteration) -> String {
+    let pv: Vec<String> = iteration.pv.iter().map(|m| m.to_coord_string()).collect();
+
+    format!(
+        "info depth {} multipv {} score {} nodes {} time {} pv {}",
+        iteration.depth,
+        iteration.line,
+        score_string(iteration.score),
+        iteration.nodes,
+        iteration.time.as_millis(),
+        pv.join(" ")
+    )
+}
+
+/// The `bestmove` reply for a search result, with the expected answer to ponder on.
+pub fn best_move_message(setup: &GameSetup, best_move: Option<&ChessMove>) -> String {
+    let best_move = match best_move {
+        Some(best_move) => best_move,
+        None => return "bestmove 0000".to_owned(),
+    };
+
+    let mut state = setup.game_state.clone();
+    state.do_move(best_move);
+
+    match setup.engine.expected_reply(&mut state) {
+        Some(reply) => format!("bestmove {} ponder {}", best_move.to_coord_string(), reply.to_coord_string()),
+        None => format!("bestmove {}", best_move.to_coord_string()),
+    }
+}
+
+/// Search limits of a `go` command, the ones not listed here are ignored.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct GoParams {
+    pub ponder: bool,
+    pub infinite: bool,
+    pub depth: Option<u32>,
+}
+
+impl GoParams {
+    pub fn parse(argument: &str) -> Result<GoParams, String> {
+        let mut params = GoParams::default();
+        let mut args = argument.split_whitespace();
+
+        while let Some(arg) = args.next() {
+            match arg {
+                "ponder" => params.ponder = true,
+                "infinite" => params.infinite = true,
+                "depth" => {
+                    let value = args.next().ok_or("missing value for depth")?;
+                    let depth = value.parse().ok().filter(|d| *d > 0).ok_or(format!("invalid depth: {}", value))?;
+                    params.depth = Some(depth);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(params)
+    }
+}
+
+/// Searches the current position until it is done or stopped. A `go ponder` search
+/// keeps going until `ponderhit` or `stop`, and a `go infinite` one until `stop`,
+/// and only then reports its move.
+/// Returns the command which stopped the search, if it still needs handling.
+fn go(setup: &mut GameSetup, params: &GoParams) -> Option<(String, String)> {
+    let wait_for_input = params.ponder || params.infinite;
+    let max_depth = setup.engine.params.max_depth;
+
+    if let Some(depth) = params.depth {
+        setup.engine.params.max_depth = depth.min(INFINITE_DEPTH);
+    }
+
+    setup.engine.set_game_keys(setup.history_keys());
+    setup.engine.set_infinite(params.infinite);
+    setup.engine.set_listener(Some(Box::new(|iteration| send_message(info_message(iteration).as_str()))));
+
+    let background = search_in_background(&mut setup.engine, &setup.game_state, wait_for_input, |command, _| {
+        match command {
+            "isready" => {
+                send_message("readyok");
+                InputAction::Ignore
+            }
+            // an infinite search still waits for the stop
+            "ponderhit" if params.ponder && !params.infinite => InputAction::Finish,
+            "stop" | "quit" => InputAction::Abort,
+            _ => InputAction::Ignore,
+        }
+    });
+
+    setup.engine.set_infinite(false);
+    setup.engine.params.max_depth = max_depth;
+
+    send_message(best_move_message(setup, background.best_move.as_ref()).as_str());
+
+    background.pending
+}
+
+/// Runs the UCI protocol until `quit`, after the `uci` command has been received.
+pub fn run(setup: &mut GameSetup) {
+    send_message("id name chessbot");
+    send_message("id author Stanislav Golubtsov");
+    for option in OPTIONS {
+        send_message(option.uci_declaration().as_str());
+    }
+
+    send_message("uciok");
+
+    // command which stopped the last search
+    let mut pending: Option<(String, String)> = None;
+
+    loop {
+        let input = pending.take().unwrap_or_else(get_message);
+        let command = input.0.as_str();
+        let argument = input.1.as_str();
+
+        match command {
+            "isready" => send_message("readyok"),
+
+            "ucinewgame" => {
+                setup.game_state = ChessState::new_game();
+                setup.engine.clear_hash();
+            }
+
+            "position" => {
+                if let Err(msg) = set_position(setup, argument) {
+                    error!("{}", msg);
+                }
+            }
+
+            "setoption" => {
+                if let Err(msg) = set_option(setup, argument) {
+                    error!("{}", msg);
+                }
+            }
+
+            "go" => match GoParams::parse(argument) {
+                Ok(params) => pending = go(setup, &params),
+                Err(msg) => error!("{}", msg),
+            },
+
+            // only meaningful during a search
+            "stop" | "ponderhit" => {}
+
+            "stats" => {
+                for line in setup.engine.stats().to_string().lines() {
+                    send_message(format!("info string {}", line).as_str());
+                }
+            }
+
+            "quit" => return,
+
+            "" => {}
+
+            _ => warn!("# unknown command: {}", command),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_and_options() {
+        let mut setup = GameSetup::new();
+
+        let same_position = |state: &ChessState, fen: &str| state.hash_key() == ChessState::from_fen(fen).hash_key();
+
+        set_position(&mut setup, "startpos moves e2e4 e7e5 g1f3").unwrap();
+        assert!(same_position(&setup.game_state, "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"));
+
+        set_position(&mut setup, "fen 4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 moves e2e4").unwrap();
+        assert!(same_position(&setup.game_state, "4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1"));
+
+        assert!(set_position(&mut setup, "startpos moves e2e5").is_err());
+
+        set_option(&mut setup, "name Threads value 4").unwrap();
+        assert_eq!(setup.engine.threads(), 4);
+
+        set_option(&mut setup, "name Hash value 2").unwrap();
+
+        set_option(&mut setup, "name Skill Level value 7").unwrap();
+        assert_eq!(setup.engine.skill().level(), 7);
+
+        // the Elo only counts with the limited strength
+        set_option(&mut setup, "name UCI_Elo value 1500").unwrap();
+        assert_eq!(setup.engine.skill().level(), 7);
+        set_option(&mut setup, "name UCI_LimitStrength value true").unwrap();
+        assert_eq!(setup.engine.skill().level(), 10);
+
+        set_option(&mut setup, "name Ponder value true").unwrap();
+
+        set_option(&mut setup, "name MultiPV value 3").unwrap();
+        assert_eq!(setup.engine.multi_pv(), 3);
+
+        assert!(set_option(&mut setup, "name Threads value many").is_err());
+        assert!(set_option(&mut setup, "name Style value 1").is_err());
+    }
+
+    #[test]
+    fn test_go_params() {
+        assert_eq!(GoParams::parse("").unwrap(), GoParams::default());
+
+        let params = GoParams::parse("infinite").unwrap();
+        assert!(params.infinite && !params.ponder);
+
+        let params = GoParams::parse("ponder wtime 1000 btime 1000 depth 6").unwrap();
+        assert!(params.ponder && !params.infinite);
+        assert_eq!(params.depth, Some(6));
+
+        assert!(GoParams::parse("depth").is_err());
+        assert!(GoParams::parse("depth 0").is_err());
+    }
+
+    #[test]
+    fn test_score_string() {
+        assert_eq!(score_string(-35), "cp -35");
+        assert_eq!(score_string(MATE_SCORE - 1), "mate 1");
+        assert_eq!(score_string(MATE_SCORE - 3), "mate 2");
+        assert_eq!(score_string(-MATE_SCORE + 2), "mate -1");
+    }
+
+    #[test]
+    fn test_best_move_message() {
+        let mut setup = GameSetup::new();
+        set_position(&mut setup, "fen 4k3/8/8/3q4/8/8/3R4/4K3 b - - 0 1").unwrap();
+
+        assert_eq!(best_move_message(&setup, None), "bestmove 0000");
+
+        // the search leaves the expected reply in the hash table
+        let best = setup.engine.find_best_move(&mut setup.game_state.clone()).unwrap();
+        let message = best_move_message(&setup, Some(&best));
+
+        let words: Vec<&str> = message.split_whitespace().collect();
+        assert_eq!(words.len(), 4);
+        assert_eq!(&words[..3], &["bestmove", best.to_coord_string().as_str(), "ponder"]);
+    }
+}
@@ -0,0 +1,62 @@
+use crate::bboard::*;
+use crate::state::BBPiece;
+
+const BOARD_COUNT: usize = 24;
+
+type Keys = [[u64; 64]; BOARD_COUNT];
+
+/// key xor-ed into the hash when black is to move
+pub const SIDE_KEY: u64 = 0x9d39_247e_3377_6d41u64;
+
+/// Random keys for every bit board and square. The boards which are derived
+/// from the others (all pieces, pins, attacks) get zero keys.
+static KEYS: Keys = generate_keys(0x2545_f491_4f6c_dd1du64);
+
+const fn next_random(seed: u64) -> u64 {
+    // xorshift64*
+    let mut x = seed;
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    x.wrapping_mul(0x2545_f491_4f6c_dd1du64)
+}
+
+const fn is_hashed(board: usize) -> bool {
+    let board = board % (BOARD_COUNT / 2);
+
+    board != BBPiece::WAll as usize && board != BBPiece::WPins as usize && board != BBPiece::WAttacks as usize
+}
+
+const fn generate_keys(seed: u64) -> Keys {
+    let mut keys = [[0u64; 64]; BOARD_COUNT];
+    let mut random = seed;
+
+    let mut board = 0;
+    while board < BOARD_COUNT {
+        let mut square = 0;
+        while square < 64 {
+            random = next_random(random);
+
+            if is_hashed(board) {
+                keys[board][square] = random;
+            }
+
+            square += 1;
+        }
+        board += 1;
+    }
+
+    keys
+}
+
+/// Hash change for toggling the given squares of a bit board.
+#[inline]
+pub fn delta_key(piece: BBPiece, delta: BBoard) -> u64 {
+    if !is_hashed(piece.idx()) {
+        return 0;
+    }
+
+    let keys = &KEYS[piece.idx()];
+
+    Bitboard(delta).into_iter().fold(0u64, |result, square| result ^ keys[square.idx()])
+}